@@ -0,0 +1,201 @@
+use rand_core::CryptoRngCore;
+use ssh_key::{private::RsaKeypair, public::KeyData, Algorithm, EcdsaCurve, HashAlg, PrivateKey};
+use std::{fmt, str::FromStr};
+
+/** The key algorithm families that can be benchmarked.  Names are matched
+ * case-insensitively on the command line, so "RSA", "Rsa" and "rsa" are all
+ * accepted.  Anything else is rejected rather than silently ignored.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyAlg {
+    Rsa,
+    Ecdsa,
+    Ed25519,
+}
+
+impl KeyAlg {
+    pub const ALL: [KeyAlg; 3] = [KeyAlg::Rsa, KeyAlg::Ecdsa, KeyAlg::Ed25519];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyAlg::Rsa => "rsa",
+            KeyAlg::Ecdsa => "ecdsa",
+            KeyAlg::Ed25519 => "ed25519",
+        }
+    }
+}
+
+impl fmt::Display for KeyAlg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for KeyAlg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        KeyAlg::ALL.into_iter()
+            .find(|a| a.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown algorithm '{}' (valid options: rsa, ecdsa, ed25519)", s))
+    }
+}
+
+/** One concrete kind of key to generate: an algorithm family together with
+ * the ssh-key algorithm (curve, hash) and key size it is generated with.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeySpec {
+    pub family: KeyAlg,
+    pub algorithm: Algorithm,
+    pub key_size: u32,      // bits
+}
+
+impl KeySpec {
+    // The hash only selects the signature algorithm (rsa-sha2-256 or
+    // rsa-sha2-512) the key signs with, e.g. as a CA; keys are generated the
+    // same with or without one, so benchmarked specs leave it out.
+    pub fn rsa(bits: u32, hash: Option<HashAlg>) -> KeySpec {
+        KeySpec {
+            family: KeyAlg::Rsa,
+            algorithm: Algorithm::Rsa {hash},
+            key_size: bits,
+        }
+    }
+
+    pub fn ecdsa(curve: EcdsaCurve) -> KeySpec {
+        KeySpec {
+            family: KeyAlg::Ecdsa,
+            algorithm: Algorithm::Ecdsa {curve},
+            key_size: curve_size(curve),
+        }
+    }
+
+    pub fn ed25519() -> KeySpec {
+        KeySpec {
+            family: KeyAlg::Ed25519,
+            algorithm: Algorithm::Ed25519,
+            key_size: 256,
+        }
+    }
+
+    pub fn curve(&self) -> Option<EcdsaCurve> {
+        match self.algorithm {
+            Algorithm::Ecdsa {curve} => Some(curve),
+            _ => None,
+        }
+    }
+
+    pub fn hash(&self) -> Option<HashAlg> {
+        match self.algorithm {
+            Algorithm::Rsa {hash} => hash,
+            _ => None,
+        }
+    }
+
+    // Whether keys of this spec can verify signatures.
+    pub fn can_verify(&self) -> bool {
+        self.family != KeyAlg::Rsa || self.key_size <= RSA_VERIFY_MAX_BITS
+    }
+
+    // Generate a new private key.  PrivateKey::random() always generates
+    // 4096 bit RSA keys, so RSA keypairs are generated directly at the
+    // requested modulus size.
+    pub fn generate(&self, rng: &mut impl CryptoRngCore) -> ssh_key::Result<PrivateKey> {
+        match self.family {
+            KeyAlg::Rsa => Ok(PrivateKey::from(RsaKeypair::random(rng, self.key_size as usize)?)),
+            _ => PrivateKey::random(rng, self.algorithm.clone()),
+        }
+    }
+}
+
+/** Specs can also be written as strings, which is how library users and
+ * services name the key they want:
+ *
+ * ```text
+ * ed25519
+ * ecdsa[-<curve>]             e.g. ecdsa-p384, defaults to p256
+ * rsa[-<bits>[-<hash>]]       e.g. rsa-3072 or rsa-3072-sha512, defaults to 4096
+ * ```
+ */
+impl fmt::Display for KeySpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.family, self.curve(), self.hash()) {
+            (_, Some(curve), _) => write!(f, "ecdsa-{}", curve.as_str().trim_start_matches("nist")),
+            (KeyAlg::Rsa, _, Some(hash)) => write!(f, "rsa-{}-{}", self.key_size, hash),
+            (KeyAlg::Rsa, _, None) => write!(f, "rsa-{}", self.key_size),
+            _ => f.write_str(self.family.name()),
+        }
+    }
+}
+
+impl FromStr for KeySpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split('-');
+        let family: KeyAlg = parts.next().unwrap_or_default().parse()?;
+        let spec = match family {
+            KeyAlg::Ed25519 => KeySpec::ed25519(),
+            KeyAlg::Ecdsa => KeySpec::ecdsa(parts.next().map(parse_curve).transpose()?
+                                                .unwrap_or(EcdsaCurve::NistP256)),
+            KeyAlg::Rsa => {
+                let bits = match parts.next() {
+                    Some(b) => b.parse::<u32>().ok()
+                        .filter(|b| (RSA_MIN_BITS..=RSA_MAX_BITS).contains(b))
+                        .ok_or_else(|| format!("invalid RSA key size '{}' (valid range: {}-{})",
+                                               b, RSA_MIN_BITS, RSA_MAX_BITS))?,
+                    None => 4096,
+                };
+                KeySpec::rsa(bits, parts.next().map(parse_hash).transpose()?)
+            }
+        };
+        match parts.next() {
+            Some(_) => Err(format!("invalid key spec '{}'", s)),
+            None => Ok(spec),
+        }
+    }
+}
+
+pub const RSA_MIN_BITS: u32 = 2048;
+pub const RSA_MAX_BITS: u32 = 16384;
+
+// The largest RSA modulus signatures can be verified with.  ssh-key converts
+// RSA public keys with rsa::RsaPublicKey::new(), which refuses larger keys, so
+// they can be generated and sign but not verify.
+pub const RSA_VERIFY_MAX_BITS: u32 = 4096;
+pub const ALL_RSA_HASHES: [HashAlg; 2] = [HashAlg::Sha256, HashAlg::Sha512];
+
+// Whether signatures made with the key can be verified.
+pub fn can_verify(key: &KeyData) -> bool {
+    !matches!(key, KeyData::Rsa(_)) || crate::fingerprint::key_bits(key) <= RSA_VERIFY_MAX_BITS
+}
+
+// Parse an RSA signature hash name, accepting "sha256" and "sha-256" spellings.
+pub fn parse_hash(s: &str) -> Result<HashAlg, String> {
+    let name = s.to_ascii_lowercase().replace('-', "");
+    ALL_RSA_HASHES.into_iter()
+        .find(|h| h.as_str() == name)
+        .ok_or_else(|| format!("unknown hash '{}' (valid options: sha256, sha512)", s))
+}
+
+pub const ALL_CURVES: [EcdsaCurve; 3] = [EcdsaCurve::NistP256, EcdsaCurve::NistP384, EcdsaCurve::NistP521];
+
+// Key size in bits of the given curve.
+pub fn curve_size(curve: EcdsaCurve) -> u32 {
+    match curve {
+        EcdsaCurve::NistP256 => 256,
+        EcdsaCurve::NistP384 => 384,
+        EcdsaCurve::NistP521 => 521,
+    }
+}
+
+// Parse a curve name.  Besides ssh-key's "nistp256" style identifiers, the
+// shorter "p256" and "P-256" spellings are accepted.
+pub fn parse_curve(s: &str) -> Result<EcdsaCurve, String> {
+    let name = s.to_ascii_lowercase().replace('-', "");
+    let name = name.strip_prefix("nist").unwrap_or(&name);
+    ALL_CURVES.into_iter()
+        .find(|c| c.as_str().strip_prefix("nist") == Some(name))
+        .ok_or_else(|| format!("unknown curve '{}' (valid options: p256, p384, p521)", s))
+}
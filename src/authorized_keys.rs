@@ -0,0 +1,196 @@
+use ssh_key::{authorized_keys::Entry, AuthorizedKeys, PublicKey};
+use std::{fs, io, path::Path};
+
+use crate::export::write_file;
+
+/** The options put in front of every key in a generated authorized_keys
+ * file, as described in sshd(8):
+ *
+ *  from               - from="pattern-list", the hosts the key may log in from.
+ *  command            - command="command", forced on every login with the key.
+ *  no_port_forwarding - no-port-forwarding.
+ *  expiry_time        - expiry-time="YYYYMMDD[HHMM[SS]][Z]", after which the key
+ *                       is refused.
+ *
+ * ssh-key's parser doesn't accept spaces, double quotes or some other
+ * characters inside option values, so the options are checked up front by
+ * parsing an entry with them.  Nor can it parse an entry with options but no
+ * comment, so keys need comments when there are options.
+ */
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct KeyOptions {
+    pub from: Option<String>,
+    pub command: Option<String>,
+    pub no_port_forwarding: bool,
+    pub expiry_time: Option<String>,
+}
+
+impl KeyOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [("from", &self.from), ("command", &self.command), ("expiry-time", &self.expiry_time)] {
+            if let Some(value) = value {
+                if value.is_empty() || value.contains(|c: char| c == '"' || c.is_whitespace() || c.is_control()) {
+                    return Err(format!("invalid {} value '{}': it must be non-empty without spaces or quotes",
+                                       name, value));
+                }
+            }
+        }
+        if let Some(expiry) = &self.expiry_time {
+            let digits = expiry.strip_suffix(['Z', 'z']).unwrap_or(expiry);
+            if ![8, 12, 14].contains(&digits.len()) || !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(format!("invalid expiry-time '{}' (expected YYYYMMDD[HHMM[SS]][Z])", expiry));
+            }
+        }
+        let opts = self.to_opts_string();
+        if !opts.is_empty() {
+            let parsed = format!("{} {}", opts, CHECK_KEY).parse::<Entry>();
+            if !parsed.is_ok_and(|entry| entry.config_opts().as_str() == opts) {
+                return Err(format!("invalid options '{}': ssh-key can't parse them", opts));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == KeyOptions::default()
+    }
+
+    // The comma separated options string, e.g. from="10.0.0.0/8",no-port-forwarding.
+    pub fn to_opts_string(&self) -> String {
+        let mut opts = Vec::new();
+        if let Some(from) = &self.from {
+            opts.push(format!("from=\"{}\"", from));
+        }
+        if let Some(command) = &self.command {
+            opts.push(format!("command=\"{}\"", command));
+        }
+        if self.no_port_forwarding {
+            opts.push("no-port-forwarding".to_string());
+        }
+        if let Some(expiry) = &self.expiry_time {
+            opts.push(format!("expiry-time=\"{}\"", expiry));
+        }
+        opts.join(",")
+    }
+}
+
+// A key to check options with, since an entry can't be parsed without one.
+const CHECK_KEY: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIP4V/7h7hnUHMPZot7PeOGaTQJuLozP3uT/7Dca6sGco check";
+
+// The authorized_keys file contents: one line per key, each with the options.
+pub fn render(keys: &[PublicKey], options: &KeyOptions) -> Result<String, ssh_key::Error> {
+    let opts = options.to_opts_string();
+    let mut contents = String::new();
+    for key in keys {
+        if !opts.is_empty() {
+            contents += &opts;
+            contents.push(' ');
+        }
+        contents += &key.to_openssh()?;
+        contents.push('\n');
+    }
+    Ok(contents)
+}
+
+// Parse the contents back with ssh-key and check every entry has the expected
+// key, comment and options, returning a description of the first difference.
+pub fn verify(contents: &str, keys: &[PublicKey], options: &KeyOptions) -> Result<(), String> {
+    let opts = options.to_opts_string();
+    let mut entries = 0;
+    for (i, entry) in AuthorizedKeys::new(contents).enumerate() {
+        let line = i + 1;
+        let entry = entry.map_err(|e| format!("line {}: unable to parse entry: {}", line, e))?;
+        let Some(expected) = keys.get(i) else {
+            return Err(format!("line {}: unexpected entry", line));
+        };
+        if entry.public_key() != expected {
+            return Err(format!("line {}: key or comment doesn't match the generated key", line));
+        }
+        if entry.config_opts().as_str() != opts {
+            return Err(format!("line {}: options '{}' don't match '{}'", line, entry.config_opts(), opts));
+        }
+        entries += 1;
+    }
+    if entries != keys.len() {
+        return Err(format!("found {} entries for {} keys", entries, keys.len()));
+    }
+    Ok(())
+}
+
+// Write the file, owner-only as sshd expects, then read it back and verify it.
+// The contents are verified before anything is written too, so entries that
+// can't be parsed never reach the disk.  An existing file is only replaced
+// when force is set.
+pub fn write(path: &Path, keys: &[PublicKey], options: &KeyOptions, force: bool) -> io::Result<()> {
+    let contents = render(keys, options).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    verify(&contents, keys, options).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    write_file(path, contents.as_bytes(), 0o600, force)?;
+    let written = fs::read_to_string(path)?;
+    verify(&written, keys, options).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIP4V/7h7hnUHMPZot7PeOGaTQJuLozP3uT/7Dca6sGco";
+
+    fn key(comment: &str) -> PublicKey {
+        let mut key = PublicKey::from_openssh(KEY).expect("test key");
+        key.set_comment(comment);
+        key
+    }
+
+    fn options() -> KeyOptions {
+        KeyOptions {from: Some("10.0.0.0/8".into()), no_port_forwarding: true, ..Default::default()}
+    }
+
+    #[test]
+    fn entries_round_trip_with_spaced_comments() {
+        let keys = [key("alice laptop 1"), key("bob")];
+        let contents = render(&keys, &options()).expect("rendered");
+        assert!(contents.starts_with("from=\"10.0.0.0/8\",no-port-forwarding ssh-ed25519 "));
+        verify(&contents, &keys, &options()).expect("verified");
+        let contents = render(&keys, &KeyOptions::default()).expect("rendered");
+        verify(&contents, &keys, &KeyOptions::default()).expect("verified");
+    }
+
+    #[test]
+    fn entries_with_options_need_a_comment() {
+        let keys = [key("")];
+        let contents = render(&keys, &KeyOptions::default()).expect("rendered");
+        verify(&contents, &keys, &KeyOptions::default()).expect("verified");
+        let contents = render(&keys, &options()).expect("rendered");
+        assert!(verify(&contents, &keys, &options()).is_err());
+    }
+
+    #[test]
+    fn verify_notices_missing_and_changed_entries() {
+        let keys = [key("a"), key("b")];
+        let contents = render(&keys[..1], &options()).expect("rendered");
+        assert!(verify(&contents, &keys, &options()).is_err());
+        assert!(verify(&contents, &keys[..1], &KeyOptions::default()).is_err());
+    }
+
+    #[test]
+    fn validate_rejects_options_ssh_key_cant_parse() {
+        options().validate().expect("valid options");
+        for command in ["run`x`", "a b", "say\"hi\""] {
+            let bad = KeyOptions {command: Some(command.into()), ..Default::default()};
+            assert!(bad.validate().is_err(), "{}", command);
+        }
+    }
+
+    #[test]
+    fn write_only_replaces_a_file_with_force() {
+        let path = std::env::temp_dir().join(format!("sshkeytest-authorized-keys-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let keys = [key("alice")];
+        write(&path, &keys, &options(), false).expect("written");
+        let err = write(&path, &keys, &KeyOptions::default(), false).expect_err("refused");
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        write(&path, &keys, &KeyOptions::default(), true).expect("overwritten");
+        assert_eq!(fs::read_to_string(&path).expect("read"), format!("{} alice\n", KEY));
+        fs::remove_file(&path).expect("removed");
+    }
+}
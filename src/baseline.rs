@@ -0,0 +1,255 @@
+use std::path::{Path, PathBuf};
+
+use crate::report::{AlgResult, Report};
+
+/** Baselines are saved JSON reports that later runs are compared against.
+ *
+ * Each algorithm and key size present in both runs is compared on its per-key
+ * generation samples with a two-sided Mann-Whitney U test, which makes no
+ * assumption about the shape of the latency distribution (RSA's is anything
+ * but normal).  A change is only reported as a regression or improvement when
+ * the median moved by more than the threshold and the test is significant.
+ * Results generated with a different RNG or number of threads than the
+ * baseline's aren't comparable, so they are reported as mismatched instead.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Regression,
+    Improvement,
+    NoChange,
+    TooFewSamples,
+    Mismatched,
+}
+
+#[derive(Clone, Debug)]
+pub struct Comparison {
+    pub algorithm: String,
+    pub key_size: u32,
+    pub baseline_median_ns: u64,
+    pub current_median_ns: u64,
+    pub change: f64,        // relative change of the median, 0.1 is 10% slower
+    pub p_value: f64,
+    pub verdict: Verdict,
+    pub mismatch: Option<String>,   // how the runs differ, if they aren't comparable
+}
+
+// The normal approximation used by the test is poor below this many samples.
+pub const MIN_SAMPLES: usize = 5;
+
+// The file a named baseline is stored in.  Names are plain file stems so that
+// a baseline can't be written outside of the baseline directory.
+pub fn baseline_path(dir: &Path, name: &str) -> Result<PathBuf, String> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(format!("invalid baseline name '{}'", name));
+    }
+    Ok(dir.join(format!("{}.json", name)))
+}
+
+// Compare every result in the current run that also appears in the baseline.
+pub fn compare(baseline: &Report, current: &Report, threshold: f64, alpha: f64) -> Vec<Comparison> {
+    current.results.iter()
+        .filter_map(|cur| {
+            let base = baseline.results.iter()
+                .find(|b| b.algorithm == cur.algorithm && b.key_size == cur.key_size)?;
+            let mut comparison = compare_result(base, cur, threshold, alpha);
+            comparison.mismatch = mismatch(baseline, base, current, cur);
+            if comparison.mismatch.is_some() {
+                comparison.verdict = Verdict::Mismatched;
+            }
+            Some(comparison)
+        })
+        .collect()
+}
+
+// How a result was generated differently from its baseline, if it was.
+fn mismatch(baseline: &Report, base: &AlgResult, current: &Report, cur: &AlgResult) -> Option<String> {
+    let mut differences = Vec::new();
+    if baseline.rng != current.rng {
+        differences.push(format!("rng {} vs {}", baseline.rng, current.rng));
+    }
+    if base.threads != cur.threads {
+        differences.push(format!("threads {} vs {}", base.threads, cur.threads));
+    }
+    if differences.is_empty() {None} else {Some(differences.join(", "))}
+}
+
+fn compare_result(base: &AlgResult, cur: &AlgResult, threshold: f64, alpha: f64) -> Comparison {
+    let base_median = base.summary.median_ns;
+    let cur_median = cur.summary.median_ns;
+    let change = if base_median == 0 {0.0} else {cur_median as f64 / base_median as f64 - 1.0};
+    let p_value = mann_whitney(&base.samples_ns, &cur.samples_ns);
+
+    let verdict = if base.samples_ns.len() < MIN_SAMPLES || cur.samples_ns.len() < MIN_SAMPLES {
+        Verdict::TooFewSamples
+    } else if p_value >= alpha || change.abs() <= threshold {
+        Verdict::NoChange
+    } else if change > 0.0 {
+        Verdict::Regression
+    } else {
+        Verdict::Improvement
+    };
+
+    Comparison {
+        algorithm: cur.algorithm.clone(),
+        key_size: cur.key_size,
+        baseline_median_ns: base_median,
+        current_median_ns: cur_median,
+        change,
+        p_value,
+        verdict,
+        mismatch: None,
+    }
+}
+
+pub fn print_comparisons(baseline_name: &str, comparisons: &[Comparison]) {
+    println!("\nComparison with baseline '{}':", baseline_name);
+    println!("  {:<28} {:>14} {:>14} {:>9} {:>8}  verdict", "keys", "baseline p50", "current p50",
+             "change", "p-value");
+    for c in comparisons {
+        let verdict = match c.verdict {
+            Verdict::Regression => "REGRESSION".to_string(),
+            Verdict::Improvement => "improvement".to_string(),
+            Verdict::NoChange => "no change".to_string(),
+            Verdict::TooFewSamples => "too few samples".to_string(),
+            Verdict::Mismatched => format!("not comparable ({})", c.mismatch.as_deref().unwrap_or("")),
+        };
+        println!("  {:<28} {:>14} {:>14} {:>+8.1}% {:>8.4}  {}",
+                 format!("{} bit {}", c.key_size, c.algorithm),
+                 format!("{:?}", std::time::Duration::from_nanos(c.baseline_median_ns)),
+                 format!("{:?}", std::time::Duration::from_nanos(c.current_median_ns)),
+                 c.change * 100.0, c.p_value, verdict);
+    }
+}
+
+// Two-sided p-value of the Mann-Whitney U test using the normal approximation
+// with tie and continuity corrections.  Returns 1 when there is nothing to test.
+pub fn mann_whitney(a: &[u64], b: &[u64]) -> f64 {
+    let (n1, n2) = (a.len() as f64, b.len() as f64);
+    if a.is_empty() || b.is_empty() {
+        return 1.0;
+    }
+
+    let (u, tie_term) = u_statistic(a, b);
+    let mean = n1 * n2 / 2.0;
+    let total = n1 + n2;
+    let variance = n1 * n2 / 12.0 * ((total + 1.0) - tie_term / (total * (total - 1.0)));
+    if variance <= 0.0 {
+        return 1.0;
+    }
+    let z = ((u - mean).abs() - 0.5).max(0.0) / variance.sqrt();
+    erfc(z / std::f64::consts::SQRT_2).min(1.0)
+}
+
+// The U statistic of a, and the sum of t^3 - t over each group of t tied
+// values for the tie correction.
+fn u_statistic(a: &[u64], b: &[u64]) -> (f64, f64) {
+    // Rank the combined samples, giving tied values their average rank.
+    let mut all: Vec<(u64, bool)> = a.iter().map(|v| (*v, true))
+        .chain(b.iter().map(|v| (*v, false)))
+        .collect();
+    all.sort_by_key(|(v, _)| *v);
+    let n = all.len();
+    let mut rank_sum_a = 0.0;
+    let mut tie_term = 0.0;
+    let mut i = 0;
+    while i < n {
+        let mut j = i;
+        while j + 1 < n && all[j + 1].0 == all[i].0 {
+            j += 1;
+        }
+        let avg_rank = (i + j) as f64 / 2.0 + 1.0;
+        rank_sum_a += avg_rank * all[i..=j].iter().filter(|(_, in_a)| *in_a).count() as f64;
+        let t = (j - i + 1) as f64;
+        tie_term += t * t * t - t;
+        i = j + 1;
+    }
+
+    let n1 = a.len() as f64;
+    (rank_sum_a - n1 * (n1 + 1.0) / 2.0, tie_term)
+}
+
+// Complementary error function (Numerical Recipes' erfcc), accurate to 1.2e-7.
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let r = t * (-z * z - 1.26551223 + t * (1.00002368 + t * (0.37409196 + t * (0.09678418
+        + t * (-0.18628806 + t * (0.27886807 + t * (-1.13520398 + t * (1.48851587
+        + t * (-0.82215223 + t * 0.17087277))))))))).exp();
+    if x >= 0.0 {r} else {2.0 - r}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report::SummaryNs;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance, "{} is not within {} of {}", actual, tolerance, expected);
+    }
+
+    #[test]
+    fn erfc_matches_known_values() {
+        assert_close(erfc(0.0), 1.0, 1e-7);
+        assert_close(erfc(0.5), 0.4795001222, 1e-7);
+        assert_close(erfc(1.0), 0.1572992071, 1e-7);
+        assert_close(erfc(2.0), 0.0046777350, 1e-8);
+        assert_close(erfc(-1.0), 1.8427007929, 1e-7);
+    }
+
+    #[test]
+    fn u_of_a_small_textbook_sample() {
+        // Combined ranks of a are 5, 7, 3, 9 and 8, summing to 32.
+        let (a, b) = ([19, 22, 16, 29, 24], [20, 11, 17, 12]);
+        assert_eq!(u_statistic(&a, &b), (17.0, 0.0));
+        assert_eq!(u_statistic(&b, &a), (3.0, 0.0));
+        assert_close(mann_whitney(&a, &b), 0.111347, 1e-6);
+        assert_close(mann_whitney(&b, &a), 0.111347, 1e-6);
+    }
+
+    #[test]
+    fn completely_separated_samples_are_significant() {
+        let (a, b) = ([1, 2, 3, 4, 5], [6, 7, 8, 9, 10]);
+        assert_eq!(u_statistic(&a, &b).0, 0.0);
+        assert_close(mann_whitney(&a, &b), 0.012186, 1e-6);
+    }
+
+    #[test]
+    fn ties_get_average_ranks_and_shrink_the_variance() {
+        // Ties: two 2s, four 3s, three 4s and two 5s.
+        let (a, b) = ([1, 2, 2, 3, 3, 3, 4], [3, 4, 4, 5, 5, 6]);
+        assert_eq!(u_statistic(&a, &b), (3.5, 6.0 + 60.0 + 24.0 + 6.0));
+        assert_close(mann_whitney(&a, &b), 0.013000, 1e-6);
+    }
+
+    #[test]
+    fn identical_inputs_show_no_difference() {
+        let a = [5, 9, 3, 7, 11, 4];
+        assert_close(mann_whitney(&a, &a), 1.0, 1e-7);
+        assert_eq!(mann_whitney(&[8; 10], &[8; 10]), 1.0);
+        assert_eq!(mann_whitney(&a, &[]), 1.0);
+    }
+
+    fn report(rng: &str, threads: usize, samples_ns: &[u64]) -> Report {
+        let summary = SummaryNs {count: samples_ns.len(), median_ns: samples_ns[samples_ns.len() / 2], ..Default::default()};
+        serde_json::from_value(serde_json::json!({
+            "timestamp": 0, "build_profile": "release", "rng": rng, "seed": null,
+            "results": [{"family": "ed25519", "algorithm": "ed25519", "key_size": 256, "iterations": samples_ns.len(),
+                         "threads": threads, "total_ns": 0, "keys_per_sec": 0.0, "summary": summary,
+                         "samples_ns": samples_ns}],
+        })).expect("report")
+    }
+
+    #[test]
+    fn runs_with_a_different_rng_or_thread_count_arent_compared() {
+        let baseline = report("os", 1, &[10, 11, 12, 13, 14, 15]);
+        let slower = [20, 21, 22, 23, 24, 25];
+        let same = compare(&baseline, &report("os", 1, &slower), 0.05, 0.05);
+        assert_eq!((same[0].verdict, same[0].mismatch.as_deref()), (Verdict::Regression, None));
+
+        let rng = compare(&baseline, &report("chacha20", 1, &slower), 0.05, 0.05);
+        assert_eq!((rng[0].verdict, rng[0].mismatch.as_deref()), (Verdict::Mismatched, Some("rng os vs chacha20")));
+        let both = compare(&baseline, &report("thread", 4, &slower), 0.05, 0.05);
+        assert_eq!(both[0].verdict, Verdict::Mismatched);
+        assert_eq!(both[0].mismatch.as_deref(), Some("rng os vs thread, threads 1 vs 4"));
+    }
+}
@@ -0,0 +1,292 @@
+use serde::{Deserialize, Serialize};
+use std::sync::{atomic::{AtomicU32, Ordering}, Barrier, Mutex, OnceLock};
+use std::{fmt, thread, time::{Duration, Instant}};
+
+use crate::alg::KeySpec;
+use crate::keygen::{gen_ssh_keys, GenOptions, KeyRun};
+use crate::parallel::ParallelRun;
+use crate::rng::RngConfig;
+
+// The z score for a two-sided 95% confidence interval, which the Student t
+// quantile tends to as the degrees of freedom grow.
+const Z_95: f64 = 1.959964;
+
+// Student t quantiles for a two-sided 95% confidence interval with 1 to 30
+// degrees of freedom.
+const T_95: [f64; 30] = [
+    12.7062, 4.3027, 3.1824, 2.7764, 2.5706, 2.4469, 2.3646, 2.3060, 2.2622, 2.2281,
+    2.2010, 2.1788, 2.1604, 2.1448, 2.1314, 2.1199, 2.1098, 2.1009, 2.0930, 2.0860,
+    2.0796, 2.0739, 2.0687, 2.0639, 2.0595, 2.0555, 2.0518, 2.0484, 2.0452, 2.0423,
+];
+
+// Fewer samples than this give too rough an estimate of the standard deviation
+// to trust the confidence interval, so target_error can't stop a run before then.
+pub const MIN_SAMPLES: usize = 30;
+
+// The most warm-up keys generated by each worker.
+pub const MAX_WARMUP_KEYS: u32 = 10;
+
+/** How long each algorithm is benchmarked for, instead of a fixed number of
+ * keys.  Keys are generated until time has passed or, with target_error, until
+ * the 95% confidence interval on the mean generation time is within
+ * target_error of the mean (e.g. 0.02 for ±2%), whichever comes first.  The
+ * worker whose key meets the stopping rule keeps it, so a run always has at
+ * least one key; keys other workers were generating at the time are dropped.
+ *
+ * The interval uses the Student t distribution, but the reported error is
+ * only approximate: checking the stopping rule after every key stops runs
+ * early on a streak of similar samples more often than a fixed sample size
+ * would, so the interval covers the true mean less than 95% of the time.
+ *
+ * Before timing starts each worker generates at least one and up to
+ * MAX_WARMUP_KEYS keys, stopping early once a tenth of time has passed; these
+ * keys are discarded so that cold caches and lazy initialization don't skew
+ * the results.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Budget {
+    pub time: Duration,
+    pub target_error: Option<f64>,
+}
+
+/** Why a budgeted run stopped. */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    TimeBudget,
+    TargetError,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::TimeBudget => f.write_str("time budget spent"),
+            StopReason::TargetError => f.write_str("target error reached"),
+        }
+    }
+}
+
+/** The outcome of a budgeted run: the timed keys, the number of warm-up keys
+ * discarded before them, why the run stopped and the relative half-width of
+ * the final 95% confidence interval, if there were enough samples for one.
+ * The error is approximate, as explained for Budget.
+ */
+#[derive(Clone, Debug)]
+pub struct BudgetRun {
+    pub run: ParallelRun,
+    pub warmup_keys: usize,
+    pub stop: StopReason,
+    pub relative_error: Option<f64>,
+}
+
+// What the workers share: the timed samples so far and, once a worker has
+// stopped the run, why and when.
+#[derive(Default)]
+struct Progress {
+    moments: Moments,
+    stop: Option<(StopReason, Instant)>,
+    // Keys kept so far, which numbers the exported keys.
+    kept: u32,
+}
+
+// Running mean and variance of the generation times, by Welford's method, so
+// the stopping rule can be checked after every key without keeping samples.
+#[derive(Default)]
+struct Moments {
+    count: usize,
+    mean: f64,
+    m2: f64,
+}
+
+impl Moments {
+    fn add(&mut self, ns: f64) {
+        self.count += 1;
+        let delta = ns - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (ns - self.mean);
+    }
+
+    fn relative_error(&self) -> Option<f64> {
+        if self.count < 2 || self.mean <= 0.0 {
+            return None;
+        }
+        let stddev = (self.m2 / (self.count - 1) as f64).sqrt();
+        Some(t_95(self.count - 1) * stddev / (self.count as f64).sqrt() / self.mean)
+    }
+}
+
+// The Student t quantile for a two-sided 95% confidence interval with df
+// degrees of freedom: from the table up to 30, and beyond that from the
+// Cornish-Fisher expansion about Z_95 (Abramowitz and Stegun 26.7.5), which
+// is accurate to 4 decimal places there.
+fn t_95(df: usize) -> f64 {
+    if let Some(t) = df.checked_sub(1).and_then(|i| T_95.get(i)) {
+        return *t;
+    }
+    let (z, v) = (Z_95, df as f64);
+    let (z3, z5, z7, z9) = (z.powi(3), z.powi(5), z.powi(7), z.powi(9));
+    z + (z3 + z) / (4.0 * v)
+        + (5.0 * z5 + 16.0 * z3 + 3.0 * z) / (96.0 * v.powi(2))
+        + (3.0 * z7 + 19.0 * z5 + 17.0 * z3 - 15.0 * z) / (384.0 * v.powi(3))
+        + (79.0 * z9 + 776.0 * z7 + 1482.0 * z5 - 1920.0 * z3 - 945.0 * z) / (92160.0 * v.powi(4))
+}
+
+// Generate keys on the given number of threads within the budget.  Each worker
+// keeps its own RNG for the whole run, using its index as its stream.  Comment
+// indexes come from a counter the workers share, so they stay unique, though
+// dropped keys leave gaps.  Keys are only exported and printed once they are
+// kept: the first keys kept are exported, numbered in the order they were
+// kept, and the first of them is printed.  The wall time runs from the end of
+// warm-up until the run is stopped.
+pub fn gen_budgeted(budget: &Budget, threads: usize, rng_config: RngConfig, spec: &KeySpec,
+                    options: &GenOptions) -> BudgetRun {
+    let threads = threads.max(1);
+    let warmup_options = GenOptions {print_first: false, export: None, collect_keys: false, ..options.clone()};
+    let progress = Mutex::new(Progress::default());
+    let next_index = AtomicU32::new(0);
+
+    let barrier = Barrier::new(threads);
+    let start = OnceLock::new();
+
+    let worker = |i: usize| {
+        let mut rng = rng_config.new_rng(i as u64);
+
+        // A budget too long to add to the clock has no deadline, so only
+        // MAX_WARMUP_KEYS and target_error end warm-up and the run.
+        let warmup_end = Instant::now().checked_add(budget.time / 10);
+        let mut warmup_keys = 0;
+        loop {
+            gen_ssh_keys(1, &mut rng, spec, &warmup_options);
+            warmup_keys += 1;
+            if warmup_keys == MAX_WARMUP_KEYS || warmup_end.is_some_and(|end| Instant::now() >= end) {
+                break;
+            }
+        }
+
+        // Wait for every worker to warm up before any starts timing, so that
+        // warm-up keys don't compete with timed ones for the CPU.
+        barrier.wait();
+        let deadline = start.get_or_init(Instant::now).checked_add(budget.time);
+
+        let mut run = KeyRun::default();
+        let mut timed_options = GenOptions {print_first: false, defer_output: true, ..options.clone()};
+        loop {
+            timed_options.first_index = next_index.fetch_add(1, Ordering::Relaxed);
+            let mut key = gen_ssh_keys(1, &mut rng, spec, &timed_options);
+
+            // Another worker stopped the run while this key was generated;
+            // drop it so the results are the ones the stopping rule saw.
+            let mut progress = progress.lock().expect("budget lock poisoned");
+            if progress.stop.is_some() {
+                break;
+            }
+            for sample in &key.samples {
+                progress.moments.add(sample.as_nanos() as f64);
+            }
+            for output in key.outputs.drain(..) {
+                progress.kept += 1;
+                let print = options.print_first && progress.kept == 1;
+                output.write(spec, progress.kept, print, options, &mut key.failures);
+            }
+            run.append(key);
+
+            let moments = &progress.moments;
+            if budget.target_error.is_some_and(|target| {
+                moments.count >= MIN_SAMPLES && moments.relative_error().is_some_and(|e| e <= target)
+            }) {
+                progress.stop = Some((StopReason::TargetError, Instant::now()));
+                break;
+            }
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                progress.stop = Some((StopReason::TimeBudget, Instant::now()));
+                break;
+            }
+        }
+        (run, warmup_keys as usize)
+    };
+
+    let (per_thread, warmup_keys): (Vec<_>, Vec<_>) = if threads == 1 {
+        vec![worker(0)].into_iter().unzip()
+    } else {
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads).map(|i| scope.spawn(move || worker(i))).collect();
+            workers.into_iter()
+                .map(|w| w.join().expect("Key generation thread panicked"))
+                .unzip()
+        })
+    };
+
+    // Every worker stops the run or sees it stopped, so there is always a stop.
+    let progress = progress.into_inner().expect("budget lock poisoned");
+    let (stop, end) = progress.stop.expect("budgeted run never stopped");
+    let start = start.get().expect("budgeted run never started");
+    BudgetRun {
+        run: ParallelRun {threads, wall: end.duration_since(*start), per_thread},
+        warmup_keys: warmup_keys.into_iter().sum(),
+        stop,
+        relative_error: progress.moments.relative_error(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::ExportOptions;
+    use crate::rng::RngKind;
+    use std::fs;
+
+    #[test]
+    fn t_quantiles() {
+        for (df, t) in [(1, 12.7062), (10, 2.2281), (30, 2.0423), (31, 2.0395), (60, 2.0003), (120, 1.9799)] {
+            assert!((t_95(df) - t).abs() < 1e-4, "df {}: {} != {}", df, t_95(df), t);
+        }
+        assert!((t_95(1_000_000) - Z_95).abs() < 1e-5);
+    }
+
+    #[test]
+    fn relative_error_uses_the_t_quantile() {
+        let mut moments = Moments::default();
+        for ns in [90.0, 100.0, 110.0] {
+            moments.add(ns);
+        }
+        // Mean 100, standard deviation 10, so 4.3027 * 10 / sqrt(3) / 100.
+        let error = moments.relative_error().expect("enough samples");
+        assert!((error - 0.248417).abs() < 1e-5, "{}", error);
+    }
+
+    #[test]
+    fn a_budget_too_long_for_the_clock_stops_at_the_target_error() {
+        let spec: KeySpec = "ed25519".parse().expect("spec");
+        let budget = Budget {time: Duration::from_secs_f64(1e19), target_error: Some(1.0)};
+        let run = gen_budgeted(&budget, 1, RngConfig {kind: RngKind::Os, seed: None}, &spec, &GenOptions::default());
+        assert_eq!(run.stop, StopReason::TargetError);
+    }
+
+    // Every worker's kept keys count towards the export, and dropped keys
+    // are never written.
+    #[test]
+    fn exports_the_first_kept_keys_across_threads() {
+        let dir = std::env::temp_dir().join(format!("sshkeytest-budget-export-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let spec: KeySpec = "ed25519".parse().expect("spec");
+        let export = ExportOptions {dir: dir.clone(), count: 3, force: false};
+        let options = GenOptions {comment: Some("key {index}".parse().expect("template")), export: Some(export),
+                                  collect_keys: true, ..Default::default()};
+        let budget = Budget {time: Duration::from_millis(200), target_error: None};
+        let run = gen_budgeted(&budget, 2, RngConfig {kind: RngKind::Os, seed: None}, &spec, &options);
+
+        let kept: Vec<_> = run.run.per_thread.iter().flat_map(|r| &r.keys).map(|k| k.comment().to_string()).collect();
+        assert!(kept.len() >= 3, "{:?}", kept);
+        let mut names: Vec<_> = fs::read_dir(&dir).expect("export dir")
+            .map(|e| e.expect("entry").file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["id_ed25519", "id_ed25519.pub", "id_ed25519_2", "id_ed25519_2.pub",
+                           "id_ed25519_3", "id_ed25519_3.pub"]);
+        for name in ["id_ed25519.pub", "id_ed25519_2.pub", "id_ed25519_3.pub"] {
+            let line = fs::read_to_string(dir.join(name)).expect("public key");
+            assert!(kept.iter().any(|c| line.trim_end().ends_with(c.as_str())), "{} not kept: {}", name, line);
+        }
+        fs::remove_dir_all(&dir).expect("cleaned up");
+    }
+}
@@ -0,0 +1,125 @@
+use rand_core::CryptoRngCore;
+use ssh_key::{certificate::{Builder, CertType}, Certificate, Fingerprint, HashAlg, PrivateKey, PublicKey};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::alg::KeySpec;
+use crate::error::KeyError;
+use crate::sign::HashSigner;
+
+// Names the issuance and validation timings are recorded under.
+pub const OP_ISSUE: &str = "cert-issue";
+pub const OP_VALIDATE: &str = "cert-validate";
+
+// The extensions ssh-keygen adds to user certificates by default.
+pub const DEFAULT_USER_EXTENSIONS: [&str; 5] = ["permit-X11-forwarding", "permit-agent-forwarding",
+                                                "permit-port-forwarding", "permit-pty", "permit-user-rc"];
+
+/** What goes into each issued certificate.  Certificates are valid from the
+ * moment they are issued until validity has passed.
+ */
+#[derive(Clone, Debug)]
+pub struct CertOptions {
+    pub cert_type: CertType,
+    pub key_id: String,
+    pub principals: Vec<String>,
+    pub validity: Duration,
+    pub critical_options: Vec<(String, String)>,
+    pub extensions: Vec<(String, String)>,
+}
+
+/** A certificate authority that issues OpenSSH certificates for generated
+ * keys and validates them the way a server would: signature, CA fingerprint,
+ * validity window and principals.  An RSA CA signs certificates with
+ * rsa_hash (rsa-sha2-256 or rsa-sha2-512).
+ */
+#[derive(Clone, Debug)]
+pub struct CertIssuer {
+    pub ca: PrivateKey,
+    pub options: CertOptions,
+    pub rsa_hash: HashAlg,
+    ca_fingerprint: Fingerprint,
+}
+
+impl CertIssuer {
+    // RSA CAs sign with rsa-sha2-512, as ssh-keygen's do.
+    pub fn new(ca: PrivateKey, options: CertOptions) -> CertIssuer {
+        // Certificate validation only supports SHA-256 CA fingerprints.
+        let ca_fingerprint = ca.fingerprint(HashAlg::Sha256);
+        CertIssuer {ca, options, rsa_hash: HashAlg::Sha512, ca_fingerprint}
+    }
+
+    // Generate a fresh CA key for the spec and issue certificates with it,
+    // signing with the spec's hash if it has one.
+    pub fn generate(ca_spec: &KeySpec, options: CertOptions, rng: &mut impl CryptoRngCore)
+                    -> Result<CertIssuer, KeyError> {
+        let mut ca = ca_spec.generate(rng).map_err(KeyError::Generate)?;
+        ca.set_comment("sshkeytest-ca");
+        let issuer = CertIssuer::new(ca, options);
+        Ok(CertIssuer {rsa_hash: ca_spec.hash().unwrap_or(issuer.rsa_hash), ..issuer})
+    }
+
+    pub fn issue(&self, subject: &PublicKey, rng: &mut impl CryptoRngCore) -> Result<Certificate, KeyError> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        let valid_before = now.saturating_add(self.options.validity.as_secs());
+        let serial = rng.next_u64();
+
+        let mut builder = Builder::new_with_random_nonce(rng, subject.key_data().clone(), now, valid_before)
+            .map_err(KeyError::Issue)?;
+        builder.serial(serial).map_err(KeyError::Issue)?;
+        builder.cert_type(self.options.cert_type).map_err(KeyError::Issue)?;
+        builder.key_id(self.options.key_id.as_str()).map_err(KeyError::Issue)?;
+        for principal in &self.options.principals {
+            builder.valid_principal(principal.as_str()).map_err(KeyError::Issue)?;
+        }
+        for (name, data) in &self.options.critical_options {
+            builder.critical_option(name.as_str(), data.as_str()).map_err(KeyError::Issue)?;
+        }
+        for (name, data) in &self.options.extensions {
+            builder.extension(name.as_str(), data.as_str()).map_err(KeyError::Issue)?;
+        }
+        builder.comment(subject.comment()).map_err(KeyError::Issue)?;
+        builder.sign(&HashSigner::new(&self.ca, self.rsa_hash)).map_err(KeyError::Issue)
+    }
+
+    // Certificate::validate() leaves checking the principals to the caller.
+    pub fn validate(&self, cert: &Certificate) -> Result<(), KeyError> {
+        cert.validate([&self.ca_fingerprint]).map_err(KeyError::Validate)?;
+        if cert.valid_principals() != self.options.principals.as_slice() {
+            return Err(KeyError::Validate(ssh_key::Error::CertificateValidation));
+        }
+        Ok(())
+    }
+
+    // Issue and then validate a certificate, timing each step separately.
+    pub fn time_issue_and_validate(&self, subject: &PublicKey, rng: &mut impl CryptoRngCore)
+                                   -> Result<(Certificate, Duration, Duration), KeyError> {
+        let start = Instant::now();
+        let cert = self.issue(subject, rng)?;
+        let issue_time = start.elapsed();
+
+        let start = Instant::now();
+        self.validate(&cert)?;
+        let validate_time = start.elapsed();
+
+        Ok((cert, issue_time, validate_time))
+    }
+}
+
+// Parse "user" or "host".
+pub fn parse_cert_type(s: &str) -> Result<CertType, String> {
+    match s.to_ascii_lowercase().as_str() {
+        "user" => Ok(CertType::User),
+        "host" => Ok(CertType::Host),
+        _ => Err(format!("unknown certificate type '{}' (valid options: user, host)", s)),
+    }
+}
+
+// Parse a "name" or "name=value" certificate option.
+pub fn parse_option(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some(("", _)) => Err(format!("invalid option '{}'", s)),
+        Some((name, value)) => Ok((name.to_string(), value.to_string())),
+        None if s.is_empty() => Err("empty option name".to_string()),
+        None => Ok((s.to_string(), String::new())),
+    }
+}
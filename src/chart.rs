@@ -0,0 +1,240 @@
+use std::{fmt::Write, fs, io, path::{Path, PathBuf}, time::Duration};
+
+use crate::report::{AlgResult, Report};
+use crate::stats::{Histogram, Quartiles};
+
+// The width of the longest bar in an ASCII histogram, in characters.
+pub const ASCII_WIDTH: usize = 50;
+
+// SVG chart size and the margins left for titles, labels and axes.
+const WIDTH: f64 = 720.0;
+const HEIGHT: f64 = 360.0;
+const LEFT: f64 = 70.0;
+const RIGHT: f64 = 30.0;
+const TOP: f64 = 40.0;
+const BOTTOM: f64 = 60.0;
+
+const BAR_COLOR: &str = "#4c78a8";
+const BOX_COLOR: &str = "#9ecae9";
+const MEDIAN_COLOR: &str = "#e45756";
+
+// The histogram as lines of text, one per bucket, each with a bar of # scaled
+// so the fullest bucket is width characters long.
+pub fn ascii_histogram(histogram: &Histogram, width: usize) -> String {
+    let total = histogram.total().max(1) as f64;
+    let fullest = histogram.buckets.iter().map(|b| b.count).max().unwrap_or(0).max(1) as f64;
+    let mut text = String::new();
+    for b in &histogram.buckets {
+        let bar = "#".repeat((b.count as f64 / fullest * width as f64).round() as usize);
+        let _ = writeln!(text, "  {:>9} - {:<9} |{:<width$} {:>7} {:>5.1}%", format!("{:?}", b.lower),
+                         format!("{:?}", b.upper), bar, b.count, b.count as f64 / total * 100.0);
+    }
+    text
+}
+
+// A bar chart of the histogram's buckets, labelled with their lower edges.
+pub fn histogram_svg(title: &str, histogram: &Histogram) -> String {
+    let mut svg = start_svg(title);
+    let plot_width = WIDTH - LEFT - RIGHT;
+    let plot_height = HEIGHT - TOP - BOTTOM;
+    let fullest = histogram.buckets.iter().map(|b| b.count).max().unwrap_or(0).max(1);
+    let bar_width = plot_width / histogram.buckets.len().max(1) as f64;
+
+    for tick in [0, fullest / 2, fullest] {
+        let y = TOP + plot_height * (1.0 - tick as f64 / fullest as f64);
+        grid_line(&mut svg, LEFT, y, WIDTH - RIGHT, y);
+        text(&mut svg, LEFT - 8.0, y + 4.0, "end", &tick.to_string());
+    }
+    for (i, b) in histogram.buckets.iter().enumerate() {
+        let x = LEFT + bar_width * i as f64;
+        let height = plot_height * b.count as f64 / fullest as f64;
+        let _ = writeln!(svg, r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}"><title>{:?} - {:?}: {}</title></rect>"#,
+                         x + 1.0, TOP + plot_height - height, bar_width - 2.0, height, BAR_COLOR,
+                         b.lower, b.upper, b.count);
+        text(&mut svg, x, HEIGHT - BOTTOM + 18.0, "middle", &format!("{:?}", b.lower));
+    }
+    if let Some(last) = histogram.buckets.last() {
+        text(&mut svg, WIDTH - RIGHT, HEIGHT - BOTTOM + 18.0, "middle", &format!("{:?}", last.upper));
+    }
+    axis_labels(&mut svg, "latency (log buckets)", "keys");
+    end_svg(svg)
+}
+
+// A horizontal box plot of one algorithm's latencies on a linear scale, with
+// outliers drawn as dots.
+pub fn box_plot_svg(title: &str, quartiles: &Quartiles) -> String {
+    let mut svg = start_svg(title);
+    let (low, high) = (quartiles.min.as_secs_f64(), quartiles.max.as_secs_f64());
+    let span = if high > low {high - low} else {high.max(1e-9)};
+    let scale = |d: Duration| LEFT + (WIDTH - LEFT - RIGHT) * (d.as_secs_f64() - low) / span;
+
+    for i in 0..=4 {
+        let value = Duration::from_secs_f64(low + span * i as f64 / 4.0);
+        let x = scale(value);
+        grid_line(&mut svg, x, TOP, x, HEIGHT - BOTTOM);
+        text(&mut svg, x, HEIGHT - BOTTOM + 18.0, "middle", &format!("{:.2?}", value));
+    }
+    draw_box(&mut svg, quartiles, TOP + (HEIGHT - TOP - BOTTOM) / 2.0, 60.0, &scale);
+    for outlier in &quartiles.outliers {
+        let _ = writeln!(svg, r#"<circle cx="{:.1}" cy="{:.1}" r="2.5" fill="none" stroke="{}"/>"#,
+                         scale(*outlier), TOP + (HEIGHT - TOP - BOTTOM) / 2.0, BAR_COLOR);
+    }
+    axis_labels(&mut svg, "latency", "");
+    end_svg(svg)
+}
+
+// Box plots of every algorithm on one logarithmic axis, so that latencies
+// orders of magnitude apart can be compared on the same chart.
+pub fn comparison_svg(title: &str, rows: &[(String, Quartiles)]) -> String {
+    let row_height = 36.0;
+    let height = TOP + BOTTOM + row_height * rows.len().max(1) as f64;
+    let left = 170.0;
+    let mut svg = start_svg_sized(title, height);
+
+    let nanos = |d: Duration| (d.as_nanos() as f64).max(1.0);
+    let min = rows.iter().map(|(_, q)| nanos(q.min)).fold(f64::INFINITY, f64::min);
+    let max = rows.iter().map(|(_, q)| nanos(q.max)).fold(1.0, f64::max);
+    let first_decade = if min.is_finite() {min.log10().floor()} else {0.0};
+    let last_decade = max.log10().ceil().max(first_decade + 1.0);
+    let scale = |d: Duration| {
+        left + (WIDTH - left - RIGHT) * (nanos(d).log10() - first_decade) / (last_decade - first_decade)
+    };
+
+    for decade in first_decade as i32..=last_decade as i32 {
+        let value = Duration::from_nanos(10u64.pow(decade as u32));
+        let x = scale(value);
+        grid_line(&mut svg, x, TOP, x, height - BOTTOM);
+        text(&mut svg, x, height - BOTTOM + 18.0, "middle", &format!("{:?}", value));
+    }
+    for (i, (label, quartiles)) in rows.iter().enumerate() {
+        let y = TOP + row_height * (i as f64 + 0.5);
+        text(&mut svg, left - 10.0, y + 4.0, "end", label);
+        draw_box(&mut svg, quartiles, y, row_height * 0.6, &scale);
+    }
+    axis_labels_sized(&mut svg, "latency (log scale)", "", height);
+    end_svg(svg)
+}
+
+// Write a histogram and box plot for each algorithm with samples, and a
+// comparison of them all, to the directory, returning the files written.
+pub fn write_charts(dir: &Path, report: &Report) -> io::Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)?;
+    let mut written = Vec::new();
+    let mut rows = Vec::new();
+    for r in &report.results {
+        let samples: Vec<_> = r.samples_ns.iter().map(|ns| Duration::from_nanos(*ns)).collect();
+        let (Some(histogram), Some(quartiles)) = (Histogram::from_samples(&samples), Quartiles::from_samples(&samples))
+        else {
+            continue;
+        };
+        let label = format!("{} bit {}", r.key_size, r.algorithm);
+        let name = chart_name(r);
+        for (kind, svg) in [("histogram", histogram_svg(&label, &histogram)),
+                            ("boxplot", box_plot_svg(&label, &quartiles))] {
+            let path = dir.join(format!("{}-{}.svg", kind, name));
+            fs::write(&path, svg)?;
+            written.push(path);
+        }
+        rows.push((label, quartiles));
+    }
+    if !rows.is_empty() {
+        let path = dir.join("comparison.svg");
+        fs::write(&path, comparison_svg("Key generation latency", &rows))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+// A file name part for the result, e.g. ed25519, ecdsa-p384 or rsa-3072.
+fn chart_name(r: &AlgResult) -> String {
+    match (&r.curve, &r.hash) {
+        (Some(curve), _) => format!("{}-{}", r.family, curve.trim_start_matches("nist")),
+        (None, Some(hash)) => format!("{}-{}-{}", r.family, r.key_size, hash),
+        (None, None) if r.family == "rsa" => format!("{}-{}", r.family, r.key_size),
+        (None, None) => r.family.clone(),
+    }
+}
+
+// A box from q1 to q3 with the median marked and whiskers, centred on y.
+fn draw_box(svg: &mut String, q: &Quartiles, y: f64, height: f64, scale: &impl Fn(Duration) -> f64) {
+    let (top, bottom) = (y - height / 2.0, y + height / 2.0);
+    let _ = writeln!(svg, r#"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="black"/>"#,
+                     scale(q.lower_whisker), y, scale(q.upper_whisker), y);
+    for whisker in [q.lower_whisker, q.upper_whisker] {
+        let _ = writeln!(svg, r#"<line x1="{x:.1}" y1="{:.1}" x2="{x:.1}" y2="{:.1}" stroke="black"/>"#,
+                         y - height / 4.0, y + height / 4.0, x = scale(whisker));
+    }
+    let _ = writeln!(svg, r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}" stroke="black"><title>q1 {:?}, median {:?}, q3 {:?}</title></rect>"#,
+                     scale(q.q1), top, (scale(q.q3) - scale(q.q1)).max(1.0), height, BOX_COLOR,
+                     q.q1, q.median, q.q3);
+    let _ = writeln!(svg, r#"<line x1="{x:.1}" y1="{:.1}" x2="{x:.1}" y2="{:.1}" stroke="{}" stroke-width="2"/>"#,
+                     top, bottom, MEDIAN_COLOR, x = scale(q.median));
+}
+
+fn start_svg(title: &str) -> String {
+    start_svg_sized(title, HEIGHT)
+}
+
+fn start_svg_sized(title: &str, height: f64) -> String {
+    let mut svg = format!(r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="12">"#,
+                          w = WIDTH, h = height);
+    svg.push('\n');
+    let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+    let _ = writeln!(svg, r#"<text x="{:.1}" y="24" text-anchor="middle" font-size="16">{}</text>"#,
+                     WIDTH / 2.0, escape(title));
+    svg
+}
+
+fn end_svg(mut svg: String) -> String {
+    svg.push_str("</svg>\n");
+    svg
+}
+
+fn axis_labels(svg: &mut String, x_label: &str, y_label: &str) {
+    axis_labels_sized(svg, x_label, y_label, HEIGHT);
+}
+
+fn axis_labels_sized(svg: &mut String, x_label: &str, y_label: &str, height: f64) {
+    text(svg, WIDTH / 2.0, height - 16.0, "middle", x_label);
+    if !y_label.is_empty() {
+        let _ = writeln!(svg, r#"<text x="16" y="{y:.1}" text-anchor="middle" transform="rotate(-90 16 {y:.1})">{}</text>"#,
+                         escape(y_label), y = height / 2.0);
+    }
+}
+
+fn grid_line(svg: &mut String, x1: f64, y1: f64, x2: f64, y2: f64) {
+    let _ = writeln!(svg, r##"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="#ddd"/>"##, x1, y1, x2, y2);
+}
+
+fn text(svg: &mut String, x: f64, y: f64, anchor: &str, content: &str) {
+    let _ = writeln!(svg, r#"<text x="{:.1}" y="{:.1}" text-anchor="{}">{}</text>"#, x, y, anchor, escape(content));
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_svg_draws_a_bar_per_bucket() {
+        let samples: Vec<_> = [1500, 2000, 7000, 12_000, 45_000].iter().map(|n| Duration::from_nanos(*n)).collect();
+        let histogram = Histogram::from_samples(&samples).expect("samples");
+        let svg = histogram_svg("ed25519 <test>", &histogram);
+        assert!(svg.starts_with("<svg ") && svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<rect x=").count(), histogram.buckets.len());
+        assert!(svg.contains("ed25519 &lt;test&gt;"));
+    }
+
+    #[test]
+    fn ascii_histogram_has_a_line_per_bucket() {
+        let samples: Vec<_> = [1500, 2000, 2500, 7000].iter().map(|n| Duration::from_nanos(*n)).collect();
+        let histogram = Histogram::from_samples(&samples).expect("samples");
+        let text = ascii_histogram(&histogram, 10);
+        assert_eq!(text.lines().count(), histogram.buckets.len());
+        // The fullest bucket, with two samples, gets the full width.
+        assert!(text.lines().any(|l| l.contains(&format!("|{} ", "#".repeat(10)))), "{}", text);
+    }
+}
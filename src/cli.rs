@@ -0,0 +1,730 @@
+use clap::{error::ErrorKind, Args, CommandFactory, Parser, Subcommand};
+use std::{path::PathBuf, time::Duration};
+
+use ssh_key::{certificate::CertType, EcdsaCurve, HashAlg};
+
+use sshkeytest::alg::{self, KeyAlg, KeySpec};
+use sshkeytest::authorized_keys::KeyOptions;
+use sshkeytest::baseline;
+use sshkeytest::budget::Budget;
+use sshkeytest::cert::{self, CertOptions};
+use sshkeytest::encrypt::{EncryptOptions, DEFAULT_KDF_ROUNDS};
+use sshkeytest::comment::CommentTemplate;
+use sshkeytest::export::ExportOptions;
+use sshkeytest::fingerprint::FingerprintHash;
+use sshkeytest::keygen::GenOptions;
+use sshkeytest::known_hosts;
+use sshkeytest::load::{LoadConfig, LoadMode};
+use sshkeytest::pool::PoolConfig;
+use sshkeytest::server::ServerConfig;
+use sshkeytest::rng::{RngConfig, RngKind, Seed};
+use sshkeytest::sign::{self, SignOptions};
+
+/// Benchmark SSH key generation for the algorithms supported by the ssh-key crate.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+// How long --target-error may take per algorithm without --time-budget.
+const DEFAULT_TIME_BUDGET_SECS: f64 = 60.0;
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Generate keys and report how long generation takes.
+    Bench(Box<BenchArgs>),
+    /// Generate host keys for a list of hosts and write a known_hosts file for them.
+    KnownHosts(Box<KnownHostsArgs>),
+    /// Fill a key pool in the background, take keys from it and report its hit rate and refill times.
+    Pool(PoolArgs),
+    /// Serve POST /keys over HTTP as a local stand-in for the Tapis Security Kernel.
+    Serve(ServeArgs),
+    /// Load test a key generation endpoint, such as the one started by serve.
+    Load(LoadArgs),
+    /// List the algorithms and random number generators that can be selected.
+    List,
+}
+
+#[derive(Args, Debug)]
+pub struct BenchArgs {
+    /// Comma separated algorithms to test (valid options: rsa, ecdsa, ed25519).
+    #[arg(short, long = "alg", value_delimiter = ',',
+          default_values_t = [KeyAlg::Ecdsa, KeyAlg::Ed25519, KeyAlg::Rsa])]
+    pub algs: Vec<KeyAlg>,
+
+    /// Comma separated ECDSA curves to test (valid options: p256, p384, p521).
+    #[arg(long, value_delimiter = ',', value_parser = alg::parse_curve,
+          default_values_t = alg::ALL_CURVES)]
+    pub curves: Vec<EcdsaCurve>,
+
+    /// Comma separated RSA modulus sizes in bits, e.g. 2048,3072,4096,8192.  Keys above
+    /// 4096 bits can't verify signatures, so --verify skips its signature check for them,
+    /// --sign only times signing and they can't be used as a --cert CA.
+    #[arg(long, value_delimiter = ',', default_value = "4096",
+          value_parser = clap::value_parser!(u32).range(alg::RSA_MIN_BITS as i64..=alg::RSA_MAX_BITS as i64))]
+    pub rsa_bits: Vec<u32>,
+
+    /// Comma separated RSA signature hashes for --sign (valid options: sha256, sha512).
+    /// The hash doesn't affect key generation, so keys are generated once per size and
+    /// each RSA key signs with every hash, reported as separate operations per size and
+    /// hash, e.g. sign-rsa-sha2-256-1024 in the operations table and --csv-ops.
+    #[arg(long, value_delimiter = ',', value_parser = alg::parse_hash, default_value = "sha256", requires = "sign")]
+    pub rsa_hashes: Vec<HashAlg>,
+
+    /// Number of keys to generate for each algorithm without its own count.
+    #[arg(short = 'n', long, default_value_t = 1000,
+          value_parser = clap::value_parser!(u32).range(1..))]
+    pub iterations: u32,
+
+    /// Number of RSA keys to generate for each size; rsa generation is very
+    /// slow, especially in dev builds.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub rsa_iterations: u32,
+
+    /// Number of ECDSA keys to generate [default: --iterations].
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub ecdsa_iterations: Option<u32>,
+
+    /// Number of ED25519 keys to generate [default: --iterations].
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub ed25519_iterations: Option<u32>,
+
+    /// Random number generator used to generate keys
+    /// (valid options: os, thread, chacha8, chacha12, chacha20).
+    #[arg(long, default_value_t = RngKind::Os)]
+    pub rng: RngKind,
+
+    /// Seed the ChaCha generator with an integer or 64 hex digits so that runs
+    /// generate identical keys.  For test fixtures only, never for real keys.
+    #[arg(long)]
+    pub seed: Option<Seed>,
+
+    /// Number of worker threads to spread each algorithm's keys across, each with its own RNG.
+    #[arg(short = 'j', long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..))]
+    pub threads: u16,
+
+    /// Also measure throughput with 1 up to --threads threads (or every available CPU
+    /// when --threads is 1), generating the same number of keys at each thread count.
+    #[arg(long)]
+    pub scaling: bool,
+
+    /// Generate keys for this many seconds per algorithm instead of a fixed number, after
+    /// discarding a few warm-up keys.
+    #[arg(long, value_name = "SECS")]
+    pub time_budget: Option<f64>,
+
+    /// Stop each algorithm once the 95% confidence interval on its mean generation time is
+    /// within this percentage of the mean, or when --time-budget runs out [default budget: 60].
+    #[arg(long, value_name = "PCT")]
+    pub target_error: Option<f64>,
+
+    /// Number of times to retry generating a key after a failure before counting it as failed.
+    #[arg(long, default_value_t = 0)]
+    pub retries: u32,
+
+    /// Encrypt every key with this passphrase (aes256-ctr, bcrypt-pbkdf) and time
+    /// encryption and decryption separately.
+    #[arg(long, env = "SSHKEYTEST_PASSPHRASE", hide_env_values = true)]
+    pub passphrase: Option<String>,
+
+    /// Number of bcrypt-pbkdf rounds used when encrypting keys.
+    #[arg(long, default_value_t = DEFAULT_KDF_ROUNDS, requires = "passphrase",
+          value_parser = clap::value_parser!(u32).range(1..))]
+    pub kdf_rounds: u32,
+
+    /// Check that every key survives a round trip through its OpenSSH encodings and
+    /// signs correctly, dumping any key that fails to stderr.
+    #[arg(long)]
+    pub verify: bool,
+
+    /// Encode every key as OpenSSH PEM, binary wire format and a public key line, parse
+    /// each encoding back and time every step.
+    #[arg(long)]
+    pub serialize: bool,
+
+    /// Sign and verify a message of each --sign-sizes size with every key (SSHSIG, as
+    /// ssh-keygen -Y sign) and time signing and verification separately.
+    #[arg(long)]
+    pub sign: bool,
+
+    /// Comma separated sizes in bytes of the messages to sign [default: 64,1024,65536].
+    #[arg(long, value_name = "BYTES", value_delimiter = ',', requires = "sign")]
+    pub sign_sizes: Vec<usize>,
+
+    /// SSHSIG namespace to sign messages in.
+    #[arg(long, default_value = sign::DEFAULT_NAMESPACE, requires = "sign")]
+    pub namespace: String,
+
+    /// Hash applied to messages before signing (valid options: sha256, sha512).
+    #[arg(long, value_parser = alg::parse_hash, default_value = "sha512", requires = "sign")]
+    pub sign_hash: HashAlg,
+
+    /// Issue an OpenSSH certificate for every key with a CA generated at startup and
+    /// time issuance and validation separately.
+    #[arg(long)]
+    pub cert: bool,
+
+    /// Key spec for the certificate authority, e.g. ed25519, ecdsa-p384 or rsa-3072-sha512.
+    /// An RSA CA signs certificates with the spec's hash, or sha512 without one, and
+    /// can be at most 4096 bits so its certificates can be validated.
+    #[arg(long, value_name = "SPEC", default_value = "ed25519", requires = "cert")]
+    pub ca: KeySpec,
+
+    /// Certificate type (valid options: user, host).
+    #[arg(long, value_parser = cert::parse_cert_type, default_value = "user", requires = "cert")]
+    pub cert_type: CertType,
+
+    /// Key id recorded in each certificate.
+    #[arg(long, default_value = "sshkeytest", requires = "cert")]
+    pub key_id: String,
+
+    /// Comma separated principals the certificates are valid for.
+    #[arg(long = "principal", value_delimiter = ',', default_value = "testuser", requires = "cert")]
+    pub principals: Vec<String>,
+
+    /// Number of seconds certificates are valid for from the moment they are issued.
+    #[arg(long, value_name = "SECS", default_value_t = 3600, requires = "cert")]
+    pub validity: u64,
+
+    /// Critical option to add to each certificate as name or name=value, e.g.
+    /// force-command=/bin/true or source-address=10.0.0.0/8; may be repeated.
+    #[arg(long, value_name = "OPTION", value_parser = cert::parse_option, requires = "cert")]
+    pub critical_option: Vec<(String, String)>,
+
+    /// Extension to add to each certificate as name or name=value; may be repeated
+    /// [default: ssh-keygen's permit-* extensions for user certificates, none for host].
+    #[arg(long, value_name = "EXTENSION", value_parser = cert::parse_option, requires = "cert")]
+    pub extension: Vec<(String, String)>,
+
+    /// Don't print the first key generated for each algorithm.
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    /// Hash used for printed fingerprints (valid options: sha256, sha512, md5).
+    #[arg(long, default_value_t = FingerprintHash::Sha256)]
+    pub fingerprint: FingerprintHash,
+
+    /// Print the fingerprint's randomart below it, as ssh-keygen -lv does.
+    #[arg(long)]
+    pub randomart: bool,
+
+    /// Write the first --export-count keys of each algorithm to this directory as
+    /// id_<alg> and id_<alg>.pub, e.g. id_ed25519 or id_rsa-4096.
+    #[arg(long, value_name = "DIR")]
+    pub export: Option<PathBuf>,
+
+    /// Number of keys of each algorithm to export; later keys get a _<n> suffix.
+    #[arg(long, default_value_t = 1, requires = "export", value_parser = clap::value_parser!(u32).range(1..))]
+    pub export_count: u32,
+
+    /// Comment template for every key, e.g. {user}@tapis-{alg}-{index} (placeholders:
+    /// {user}, {host}, {alg}, {bits}, {index}) [default: none, or {user}@{host} with --export].
+    #[arg(long, value_name = "TEMPLATE")]
+    pub comment: Option<CommentTemplate>,
+
+    /// Overwrite existing key files when exporting, and an existing --authorized-keys file.
+    #[arg(long)]
+    pub force: bool,
+
+    /// Write every generated public key to this authorized_keys file, then parse it
+    /// back to check it.
+    #[arg(long, value_name = "FILE")]
+    pub authorized_keys: Option<PathBuf>,
+
+    /// Restrict authorized_keys entries to these hosts, as from="PATTERNS".
+    #[arg(long, value_name = "PATTERNS", requires = "authorized_keys")]
+    pub from: Option<String>,
+
+    /// Force this command for authorized_keys entries, as command="COMMAND".
+    #[arg(long, requires = "authorized_keys")]
+    pub command: Option<String>,
+
+    /// Add no-port-forwarding to authorized_keys entries.
+    #[arg(long, requires = "authorized_keys")]
+    pub no_port_forwarding: bool,
+
+    /// Expire authorized_keys entries at this time, as expiry-time="YYYYMMDD[HHMM[SS]][Z]".
+    #[arg(long, value_name = "TIMESTAMP", requires = "authorized_keys")]
+    pub expiry_time: Option<String>,
+
+    /// Write the full report, including every per-key sample, as JSON to this file.
+    #[arg(long, value_name = "FILE")]
+    pub json: Option<PathBuf>,
+
+    /// Write one row of summary statistics per algorithm as CSV to this file.
+    #[arg(long, value_name = "FILE")]
+    pub csv: Option<PathBuf>,
+
+    /// Write one row per generated key as CSV to this file.
+    #[arg(long, value_name = "FILE")]
+    pub csv_samples: Option<PathBuf>,
+
+    /// Write one row of summary statistics per algorithm and per-key operation, such as
+    /// certificate issuance, as CSV to this file.
+    #[arg(long, value_name = "FILE")]
+    pub csv_ops: Option<PathBuf>,
+
+    /// Print a histogram of each algorithm's generation latency.
+    #[arg(long)]
+    pub histogram: bool,
+
+    /// Write SVG charts to this directory: a histogram and box plot per algorithm and a
+    /// log-scale comparison of them all.
+    #[arg(long, value_name = "DIR")]
+    pub charts: Option<PathBuf>,
+
+    /// Save this run as the named baseline in --baseline-dir.
+    #[arg(long, value_name = "NAME")]
+    pub save_baseline: Option<String>,
+
+    /// Compare this run with the named baseline and exit with an error on a regression.
+    #[arg(long, value_name = "NAME")]
+    pub baseline: Option<String>,
+
+    /// Directory baselines are saved in and read from.
+    #[arg(long, value_name = "DIR", default_value = "baselines")]
+    pub baseline_dir: PathBuf,
+
+    /// Percentage the median must slow down by before it counts as a regression.
+    #[arg(long, value_name = "PCT", default_value_t = 5.0)]
+    pub threshold: f64,
+
+    /// Significance level a change must reach before it counts as a regression.
+    #[arg(long, default_value_t = 0.05)]
+    pub alpha: f64,
+}
+
+impl BenchArgs {
+    // Exit with a usage error for option combinations clap can't check itself.
+    pub fn validate(&self) {
+        // Checked first, since budget() converts --time-budget to a Duration.
+        let seconds = |v: f64| Duration::try_from_secs_f64(v).is_ok_and(|d| !d.is_zero());
+        let positive = |v: f64| v.is_finite() && v > 0.0;
+        if !self.time_budget.is_none_or(seconds) || !self.target_error.is_none_or(positive) {
+            usage_error("bench", ErrorKind::InvalidValue,
+                        "--time-budget and --target-error must be greater than 0, and --time-budget in range");
+        }
+        if self.seed.is_some() && !self.rng.is_seedable() {
+            usage_error("bench", ErrorKind::ArgumentConflict,
+                        format!("--seed requires a chacha generator, not --rng {}", self.rng));
+        }
+        if self.cert && !self.ca.can_verify() {
+            usage_error("bench", ErrorKind::InvalidValue,
+                        format!("--ca {}: certificates signed by RSA keys above {} bits can't be validated",
+                                self.ca, alg::RSA_VERIFY_MAX_BITS));
+        }
+        for name in [&self.baseline, &self.save_baseline].into_iter().flatten() {
+            if let Err(e) = baseline::baseline_path(&self.baseline_dir, name) {
+                usage_error("bench", ErrorKind::InvalidValue, e);
+            }
+        }
+        if let Some(export) = self.gen_options().export.filter(|e| !e.force) {
+            // A budgeted run may generate enough keys to export all --export-count of them.
+            let keys_per_spec = |spec: &KeySpec| match self.budget() {
+                Some(_) => u32::MAX,
+                None => self.iterations_for(spec.family),
+            };
+            let existing = export.existing(&self.key_specs(), keys_per_spec);
+            if !existing.is_empty() {
+                let files: Vec<_> = existing.iter().map(|p| p.display().to_string()).collect();
+                usage_error("bench", ErrorKind::ValueValidation,
+                            format!("refusing to overwrite {} without --force", files.join(", ")));
+            }
+        }
+        if let Some(path) = self.authorized_keys.as_ref().filter(|p| p.exists() && !self.force) {
+            usage_error("bench", ErrorKind::ValueValidation,
+                        format!("refusing to overwrite {} without --force", path.display()));
+        }
+        if let Err(e) = self.key_options().validate() {
+            usage_error("bench", ErrorKind::InvalidValue, e);
+        }
+        if !self.key_options().is_empty() && self.gen_options().comment.is_none_or(|c| c.to_string().is_empty()) {
+            usage_error("bench", ErrorKind::MissingRequiredArgument,
+                        "authorized_keys entries with options need a --comment for ssh-key to parse them");
+        }
+        if self.threshold < 0.0 || !(self.alpha > 0.0 && self.alpha < 1.0) {
+            usage_error("bench", ErrorKind::InvalidValue, "--threshold must be >= 0 and --alpha between 0 and 1");
+        }
+    }
+
+    // The time budget for each algorithm, if keys aren't generated a fixed number of times.
+    pub fn budget(&self) -> Option<Budget> {
+        if self.time_budget.is_none() && self.target_error.is_none() {
+            return None;
+        }
+        Some(Budget {
+            time: Duration::from_secs_f64(self.time_budget.unwrap_or(DEFAULT_TIME_BUDGET_SECS)),
+            target_error: self.target_error.map(|pct| pct / 100.0),
+        })
+    }
+
+    pub fn gen_options(&self) -> GenOptions {
+        GenOptions {
+            print_first: !self.quiet,
+            fingerprint: self.fingerprint,
+            randomart: self.randomart,
+            comment: self.comment.clone()
+                .or_else(|| self.export.as_ref().map(|_| CommentTemplate::default())),
+            first_index: 0,
+            export: self.export.as_ref().map(|dir| ExportOptions {
+                dir: dir.clone(),
+                count: self.export_count,
+                force: self.force,
+            }),
+            collect_keys: self.authorized_keys.is_some(),
+            defer_output: false,
+            retries: self.retries,
+            verify: self.verify,
+            encrypt: self.passphrase.as_ref().map(|p| EncryptOptions::new(p.as_str(), self.kdf_rounds)),
+            cert: None,
+            serialize: self.serialize,
+            sign: self.sign.then(|| {
+                let sizes = if self.sign_sizes.is_empty() {&sign::DEFAULT_MESSAGE_SIZES[..]} else {&self.sign_sizes};
+                SignOptions::new(self.namespace.as_str(), self.sign_hash, &self.rsa_hashes, sizes)
+            }),
+        }
+    }
+
+    // What to put in each certificate when --cert is given.
+    pub fn cert_options(&self) -> Option<CertOptions> {
+        if !self.cert {
+            return None;
+        }
+        let extensions = if !self.extension.is_empty() || self.cert_type.is_host() {
+            self.extension.clone()
+        } else {
+            cert::DEFAULT_USER_EXTENSIONS.iter().map(|e| (e.to_string(), String::new())).collect()
+        };
+        Some(CertOptions {
+            cert_type: self.cert_type,
+            key_id: self.key_id.clone(),
+            principals: self.principals.clone(),
+            validity: Duration::from_secs(self.validity),
+            critical_options: self.critical_option.clone(),
+            extensions,
+        })
+    }
+
+    // The options for each authorized_keys entry.
+    pub fn key_options(&self) -> KeyOptions {
+        KeyOptions {
+            from: self.from.clone(),
+            command: self.command.clone(),
+            no_port_forwarding: self.no_port_forwarding,
+            expiry_time: self.expiry_time.clone(),
+        }
+    }
+
+    pub fn rng_config(&self) -> RngConfig {
+        RngConfig {kind: self.rng, seed: self.seed}
+    }
+
+    // The number of keys to generate for the given algorithm.
+    pub fn iterations_for(&self, alg: KeyAlg) -> u32 {
+        match alg {
+            KeyAlg::Rsa => self.rsa_iterations,
+            KeyAlg::Ecdsa => self.ecdsa_iterations.unwrap_or(self.iterations),
+            KeyAlg::Ed25519 => self.ed25519_iterations.unwrap_or(self.iterations),
+        }
+    }
+
+    // The keys to generate for the selected algorithms in the order given,
+    // with one spec per selected curve for ECDSA, one spec per size for RSA,
+    // and without duplicates.
+    pub fn key_specs(&self) -> Vec<KeySpec> {
+        let mut specs = Vec::new();
+        for alg in &self.algs {
+            let expanded = match alg {
+                KeyAlg::Rsa => self.rsa_bits.iter().map(|bits| KeySpec::rsa(*bits, None)).collect(),
+                KeyAlg::Ecdsa => self.curves.iter().map(|c| KeySpec::ecdsa(*c)).collect(),
+                KeyAlg::Ed25519 => vec![KeySpec::ed25519()],
+            };
+            for spec in expanded {
+                if !specs.contains(&spec) {
+                    specs.push(spec);
+                }
+            }
+        }
+        specs
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct KnownHostsArgs {
+    /// Comma separated hostnames to generate host keys for, e.g. node1.cluster,[node2.cluster]:2222.
+    #[arg(long = "host", value_delimiter = ',', required = true)]
+    pub hosts: Vec<String>,
+
+    /// Comma separated key specs to generate for every host, e.g. ed25519,ecdsa-p256,rsa-3072.
+    #[arg(long = "key", value_name = "SPEC", value_delimiter = ',', default_value = "ed25519")]
+    pub keys: Vec<KeySpec>,
+
+    /// The known_hosts file to write.
+    #[arg(short, long, value_name = "FILE", default_value = "known_hosts")]
+    pub output: PathBuf,
+
+    /// Hash hostnames as ssh-keygen -H does, so the file doesn't reveal them.
+    #[arg(long)]
+    pub hash: bool,
+
+    /// Generate a host certificate authority and add an @cert-authority line trusting it
+    /// for hosts matching these comma separated patterns, e.g. *.cluster.
+    #[arg(long, value_name = "PATTERNS")]
+    pub cert_authority: Option<String>,
+
+    /// Key spec for the certificate authority.
+    #[arg(long, value_name = "SPEC", default_value = "ed25519", requires = "cert_authority")]
+    pub ca: KeySpec,
+
+    /// Write each host's keys, and certificates with --cert-authority, to DIR/<host>/ as
+    /// ssh_host_<spec>_key, ssh_host_<spec>_key.pub and ssh_host_<spec>_key-cert.pub.
+    #[arg(long, value_name = "DIR")]
+    pub key_dir: Option<PathBuf>,
+
+    /// Number of seconds host certificates are valid for.
+    #[arg(long, value_name = "SECS", default_value_t = 365 * 24 * 3600, requires = "cert_authority")]
+    pub validity: u64,
+
+    /// Overwrite the known_hosts file and key files if they exist.
+    #[arg(long)]
+    pub force: bool,
+}
+
+impl KnownHostsArgs {
+    // Exit with a usage error for hosts that would corrupt the known_hosts
+    // file, a CA whose certificates can't be validated, or an existing file.
+    pub fn validate(&self) {
+        for host in &self.hosts {
+            if let Err(e) = known_hosts::check_host(host) {
+                usage_error("known-hosts", ErrorKind::InvalidValue, e);
+            }
+        }
+        if self.cert_authority.is_some() && !self.ca.can_verify() {
+            usage_error("known-hosts", ErrorKind::InvalidValue,
+                        format!("--ca {}: certificates signed by RSA keys above {} bits can't be validated",
+                                self.ca, alg::RSA_VERIFY_MAX_BITS));
+        }
+        if self.output.exists() && !self.force {
+            usage_error("known-hosts", ErrorKind::ValueValidation,
+                        format!("refusing to overwrite {} without --force", self.output.display()));
+        }
+    }
+
+    // What to put in each host certificate; the principal is set per host.
+    pub fn cert_options(&self) -> CertOptions {
+        CertOptions {
+            cert_type: CertType::Host,
+            key_id: "sshkeytest-host".to_string(),
+            principals: Vec::new(),
+            validity: Duration::from_secs(self.validity),
+            critical_options: Vec::new(),
+            extensions: Vec::new(),
+        }
+    }
+}
+
+// Exit with a usage error from the named subcommand, so that the message is
+// followed by that subcommand's usage rather than the top-level one.  The
+// command is built first to give the subcommand its full name, e.g.
+// "sshkeytest bench".
+fn usage_error(subcommand: &str, kind: ErrorKind, message: impl std::fmt::Display) -> ! {
+    let mut cli = Cli::command();
+    cli.build();
+    cli.find_subcommand_mut(subcommand).expect("known subcommand").error(kind, message).exit()
+}
+
+// Exit with a usage error if the pool watermarks given by --low and --high
+// are out of order, as for both the pool and serve subcommands.
+fn validate_watermarks(subcommand: &str, low: usize, high: usize) {
+    if high == 0 || low > high {
+        usage_error(subcommand, ErrorKind::InvalidValue,
+                    format!("--high must be at least 1 and at least --low (got --low {} --high {})", low, high));
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct PoolArgs {
+    /// Comma separated key specs to keep pooled, e.g. ed25519,rsa-3072.
+    #[arg(long = "key", value_name = "SPEC", value_delimiter = ',', default_value = "ed25519")]
+    pub keys: Vec<KeySpec>,
+
+    /// Number of keys per spec below which the pool is refilled.
+    #[arg(long, default_value_t = 2)]
+    pub low: usize,
+
+    /// Number of keys per spec the pool is refilled up to.
+    #[arg(long, default_value_t = 8)]
+    pub high: usize,
+
+    /// Number of keys to take from the pool for each spec.
+    #[arg(short = 'n', long, default_value_t = 20)]
+    pub takes: u32,
+
+    /// Milliseconds to pause between takes, giving the pool time to refill.
+    #[arg(long, value_name = "MS", default_value_t = 0)]
+    pub interval_ms: u64,
+
+    /// Seconds to wait for the pool to fill before taking keys.
+    #[arg(long, value_name = "SECS", default_value_t = 60)]
+    pub fill_timeout: u64,
+
+    /// Comment template for every pooled key (placeholders: {user}, {host}, {alg}, {bits}, {index}).
+    #[arg(long, value_name = "TEMPLATE")]
+    pub comment: Option<CommentTemplate>,
+
+    /// Random number generator used to generate keys
+    /// (valid options: os, thread, chacha8, chacha12, chacha20).
+    #[arg(long, default_value_t = RngKind::Os)]
+    pub rng: RngKind,
+}
+
+impl PoolArgs {
+    pub fn validate(&self) {
+        validate_watermarks("pool", self.low, self.high);
+    }
+
+    pub fn pool_config(&self) -> PoolConfig {
+        PoolConfig {
+            specs: self.keys.clone(),
+            low_watermark: self.low,
+            high_watermark: self.high,
+            rng: RngConfig {kind: self.rng, seed: None},
+            options: GenOptions {comment: self.comment.clone(), ..Default::default()},
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct ServeArgs {
+    /// Address to listen on; use port 0 to pick a free port.
+    #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+    pub listen: String,
+
+    /// Number of requests to handle at a time.
+    #[arg(short = 'j', long, default_value_t = 4, value_parser = clap::value_parser!(u16).range(1..))]
+    pub threads: u16,
+
+    /// Comma separated key specs to serve from a pool of pre-generated keys, e.g. rsa-3072;
+    /// other specs are generated while the request waits.
+    #[arg(long = "pool", value_name = "SPEC", value_delimiter = ',')]
+    pub pool_keys: Vec<KeySpec>,
+
+    /// Number of pooled keys per spec below which the pool is refilled.
+    #[arg(long, default_value_t = 2)]
+    pub low: usize,
+
+    /// Number of pooled keys per spec the pool is refilled up to.
+    #[arg(long, default_value_t = 8)]
+    pub high: usize,
+
+    /// Number of bcrypt-pbkdf rounds used when a request includes a passphrase.
+    #[arg(long, default_value_t = DEFAULT_KDF_ROUNDS, value_parser = clap::value_parser!(u32).range(1..))]
+    pub kdf_rounds: u32,
+
+    /// Random number generator used to generate keys
+    /// (valid options: os, thread, chacha8, chacha12, chacha20).
+    #[arg(long, default_value_t = RngKind::Os)]
+    pub rng: RngKind,
+}
+
+impl ServeArgs {
+    pub fn validate(&self) {
+        validate_watermarks("serve", self.low, self.high);
+    }
+
+    pub fn server_config(&self) -> ServerConfig {
+        ServerConfig {
+            addr: self.listen.clone(),
+            threads: self.threads as usize,
+            kdf_rounds: self.kdf_rounds,
+            pool: PoolConfig {
+                specs: self.pool_keys.clone(),
+                low_watermark: self.low,
+                high_watermark: self.high,
+                rng: RngConfig {kind: self.rng, seed: None},
+                options: GenOptions::default(),
+            },
+        }
+    }
+}
+
+#[derive(Args, Debug)]
+pub struct LoadArgs {
+    /// URL of the key generation endpoint.
+    #[arg(long, default_value = "http://127.0.0.1:8080/keys")]
+    pub url: String,
+
+    /// Comma separated key specs to request, each load tested in turn, e.g. ed25519,rsa-3072.
+    #[arg(long = "key", value_name = "SPEC", value_delimiter = ',', default_value = "ed25519")]
+    pub keys: Vec<KeySpec>,
+
+    /// Start this many requests per second, whether or not earlier requests have finished.
+    #[arg(long, value_name = "PER_SEC", conflicts_with = "concurrency")]
+    pub rate: Option<f64>,
+
+    /// Number of clients each sending requests back to back [default: 1].
+    #[arg(short = 'c', long, value_name = "CLIENTS", value_parser = clap::value_parser!(u16).range(1..))]
+    pub concurrency: Option<u16>,
+
+    /// Seconds to load test each key spec for.
+    #[arg(short, long, value_name = "SECS", default_value_t = 10.0)]
+    pub duration: f64,
+
+    /// Most requests outstanding at once with --rate.
+    #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u16).range(1..))]
+    pub max_in_flight: u16,
+
+    /// Seconds to wait for each response before counting the request as failed.
+    #[arg(long, value_name = "SECS", default_value_t = 60)]
+    pub timeout: u64,
+
+    /// Seconds per row of the throughput over time table.
+    #[arg(long, value_name = "SECS", default_value_t = 1.0)]
+    pub interval: f64,
+
+    /// Ask for keys encrypted with this passphrase.
+    #[arg(long, env = "SSHKEYTEST_PASSPHRASE", hide_env_values = true)]
+    pub passphrase: Option<String>,
+
+    /// Write throughput, errors and latency for each interval to this CSV file.
+    #[arg(long, value_name = "FILE")]
+    pub csv_timeline: Option<PathBuf>,
+}
+
+impl LoadArgs {
+    // Exit with a usage error for durations clap can't check itself, or for
+    // RSA specs with a hash, which key requests have no field for.
+    pub fn validate(&self) {
+        let seconds = |v: f64| Duration::try_from_secs_f64(v).is_ok_and(|d| !d.is_zero());
+        if !seconds(self.duration) || !seconds(self.interval) || !self.rate.is_none_or(|r| seconds(1.0 / r)) {
+            usage_error("load", ErrorKind::InvalidValue,
+                        "--duration, --interval and --rate must be greater than 0 and in range");
+        }
+        if let Some(spec) = self.keys.iter().find(|s| s.hash().is_some()) {
+            usage_error("load", ErrorKind::InvalidValue,
+                        format!("--key {}: key requests can't ask for a signature hash; use rsa-{}",
+                                spec, spec.key_size));
+        }
+    }
+
+    pub fn mode(&self) -> LoadMode {
+        match self.rate {
+            Some(rate) => LoadMode::Rate(rate),
+            None => LoadMode::Concurrency(self.concurrency.unwrap_or(1) as usize),
+        }
+    }
+
+    pub fn load_config(&self, spec: &KeySpec) -> LoadConfig {
+        LoadConfig {
+            url: self.url.clone(),
+            body: LoadConfig::key_request(spec, self.passphrase.as_deref()),
+            mode: self.mode(),
+            duration: Duration::from_secs_f64(self.duration),
+            max_in_flight: self.max_in_flight as usize,
+            timeout: Duration::from_secs(self.timeout),
+            interval: Duration::from_secs_f64(self.interval),
+        }
+    }
+}
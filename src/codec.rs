@@ -0,0 +1,47 @@
+use ssh_key::{LineEnding, PrivateKey, PublicKey};
+use std::time::{Duration, Instant};
+
+use crate::error::KeyError;
+
+// Names the encoding and decoding timings are recorded under.
+pub const OP_ENCODE_PEM: &str = "encode-pem";
+pub const OP_DECODE_PEM: &str = "decode-pem";
+pub const OP_ENCODE_BYTES: &str = "encode-bytes";
+pub const OP_DECODE_BYTES: &str = "decode-bytes";
+pub const OP_ENCODE_PUBLIC: &str = "encode-public";
+pub const OP_DECODE_PUBLIC: &str = "decode-public";
+
+/** Time encoding the key in each of its formats and parsing the result back:
+ * the OpenSSH PEM private key, the binary wire format of the private key and
+ * the authorized_keys style public key line.  Returns the operation name and
+ * time taken for each step, e.g. ("decode-pem", 8µs).
+ */
+pub fn time_encodings(key: &PrivateKey) -> Result<Vec<(&'static str, Duration)>, KeyError> {
+    let mut timings = Vec::with_capacity(6);
+
+    let start = Instant::now();
+    let pem = key.to_openssh(LineEnding::LF).map_err(KeyError::EncodePrivate)?;
+    timings.push((OP_ENCODE_PEM, start.elapsed()));
+
+    let start = Instant::now();
+    PrivateKey::from_openssh(pem.as_bytes()).map_err(KeyError::DecodePrivate)?;
+    timings.push((OP_DECODE_PEM, start.elapsed()));
+
+    let start = Instant::now();
+    let bytes = key.to_bytes().map_err(KeyError::EncodeBytes)?;
+    timings.push((OP_ENCODE_BYTES, start.elapsed()));
+
+    let start = Instant::now();
+    PrivateKey::from_bytes(&bytes).map_err(KeyError::DecodeBytes)?;
+    timings.push((OP_DECODE_BYTES, start.elapsed()));
+
+    let start = Instant::now();
+    let line = key.public_key().to_openssh().map_err(KeyError::EncodePublic)?;
+    timings.push((OP_ENCODE_PUBLIC, start.elapsed()));
+
+    let start = Instant::now();
+    PublicKey::from_openssh(&line).map_err(KeyError::DecodePublic)?;
+    timings.push((OP_DECODE_PUBLIC, start.elapsed()));
+
+    Ok(timings)
+}
@@ -0,0 +1,165 @@
+use std::{fmt, fs, str::FromStr};
+
+use crate::alg::KeySpec;
+
+// The placeholders a comment template can use.
+const PLACEHOLDERS: [&str; 5] = ["user", "host", "alg", "bits", "index"];
+
+// The comment ssh-keygen gives new keys.
+pub const DEFAULT_TEMPLATE: &str = "{user}@{host}";
+
+/** A template for the comment set on each generated key, e.g.
+ * "{user}@tapis-{alg}-{index}".  The placeholders are:
+ *
+ *  {user}   - the user running the program.
+ *  {host}   - this host's name.
+ *  {alg}    - the key spec, e.g. ed25519, ecdsa-p384 or rsa-4096.
+ *  {bits}   - the key size in bits.
+ *  {index}  - the key's number within its spec, counting from 1.
+ *
+ * The user and host are looked up once, when the template is parsed.  Control
+ * characters are rejected, since a newline in a comment would start a new
+ * line in a public key or authorized_keys file, but only in the user and host
+ * names the template uses.  The default template can't fail, so it drops them
+ * from the user and host names instead.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommentTemplate {
+    template: String,
+    user: String,
+    host: String,
+}
+
+impl CommentTemplate {
+    pub fn render(&self, spec: &KeySpec, index: u32) -> String {
+        self.template
+            .replace("{user}", &self.user)
+            .replace("{host}", &self.host)
+            .replace("{alg}", &spec.to_string())
+            .replace("{bits}", &spec.key_size.to_string())
+            .replace("{index}", &index.to_string())
+    }
+
+    // A template whose user and host have any control characters removed.
+    fn with_clean_names(template: &str, user: &str, host: &str) -> CommentTemplate {
+        let clean = |s: &str| s.replace(char::is_control, "");
+        CommentTemplate {template: template.to_string(), user: clean(user), host: clean(host)}
+    }
+
+    // Parse a template, rejecting unknown or unterminated placeholders so
+    // typos don't end up in every key's comment, and control characters in
+    // the template and in whichever of user and host it uses.
+    fn parse(s: &str, user: String, host: String) -> Result<CommentTemplate, String> {
+        let mut rest = s;
+        while let Some(open) = rest.find('{') {
+            let Some(close) = rest[open..].find('}') else {
+                return Err(format!("unterminated placeholder in comment template '{}'", s));
+            };
+            let name = &rest[open + 1..open + close];
+            if !PLACEHOLDERS.contains(&name) {
+                return Err(format!("unknown placeholder '{{{}}}' in comment template (valid options: {})",
+                                   name, PLACEHOLDERS.map(|p| format!("{{{}}}", p)).join(", ")));
+            }
+            rest = &rest[open + close + 1..];
+        }
+        check_comment("comment template", s)?;
+        if s.contains("{user}") {
+            check_comment("user name", &user)?;
+        }
+        if s.contains("{host}") {
+            check_comment("host name", &host)?;
+        }
+        Ok(CommentTemplate {template: s.to_string(), user, host})
+    }
+}
+
+impl Default for CommentTemplate {
+    fn default() -> Self {
+        CommentTemplate::with_clean_names(DEFAULT_TEMPLATE, &user(), &host())
+    }
+}
+
+impl fmt::Display for CommentTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.template)
+    }
+}
+
+impl FromStr for CommentTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CommentTemplate::parse(s, user(), host())
+    }
+}
+
+// Reject a comment, or part of one, with control characters, naming it as what.
+pub fn check_comment(what: &str, value: &str) -> Result<(), String> {
+    if value.contains(|c: char| c.is_control()) {
+        return Err(format!("invalid {} '{}': it must not contain control characters", what, value.escape_debug()));
+    }
+    Ok(())
+}
+
+fn user() -> String {
+    std::env::var("USER").or_else(|_| std::env::var("LOGNAME")).unwrap_or_else(|_| "user".into())
+}
+
+fn host() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| fs::read_to_string("/etc/hostname"))
+        .map(|h| h.trim().to_string())
+        .ok()
+        .filter(|h| !h.is_empty())
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .unwrap_or_else(|| "localhost".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_template_drops_control_characters() {
+        let template = CommentTemplate::with_clean_names(DEFAULT_TEMPLATE, "al\nice", "ho\tst\r");
+        assert_eq!(template.render(&"ed25519".parse().expect("spec"), 1), "alice@host");
+    }
+
+    #[test]
+    fn renders_every_placeholder() {
+        let template = CommentTemplate::with_clean_names("{user}@{host} {alg} {bits} #{index}", "alice", "tapis");
+        assert_eq!(template.render(&"rsa-3072".parse().expect("spec"), 7), "alice@tapis rsa-3072 3072 #7");
+        assert_eq!(template.render(&"ecdsa-p384".parse().expect("spec"), 12), "alice@tapis ecdsa-p384 384 #12");
+    }
+
+    #[test]
+    fn parses_templates() {
+        let template: CommentTemplate = "{user}@tapis-{alg}-{index}".parse().expect("template");
+        assert_eq!(template.to_string(), "{user}@tapis-{alg}-{index}");
+        assert_eq!(template.render(&"ed25519".parse().expect("spec"), 2), format!("{}@tapis-ed25519-2", user()));
+        let plain: CommentTemplate = "no placeholders".parse().expect("template");
+        assert_eq!(plain.render(&"ed25519".parse().expect("spec"), 1), "no placeholders");
+    }
+
+    #[test]
+    fn rejects_bad_templates() {
+        let unknown = "{user}-{nmae}".parse::<CommentTemplate>().expect_err("unknown placeholder");
+        assert!(unknown.contains("unknown placeholder '{nmae}'"), "{}", unknown);
+        let unterminated = "{user}-{index".parse::<CommentTemplate>().expect_err("unterminated placeholder");
+        assert!(unterminated.contains("unterminated"), "{}", unterminated);
+        for bad in ["alice\n@tapis", "alice\t{index}", "\u{1b}[31m"] {
+            let error = bad.parse::<CommentTemplate>().expect_err("control characters");
+            assert!(error.contains("control characters"), "{}", error);
+        }
+    }
+
+    // Control characters in the user or host name only matter to templates
+    // that use them.
+    #[test]
+    fn checks_only_the_names_a_template_uses() {
+        let parse = |s: &str| CommentTemplate::parse(s, "al\nice".into(), "ho\tst".into());
+        assert!(parse("tapis-{alg}-{index}").is_ok());
+        assert!(parse("{user}").expect_err("user name").contains("user name"));
+        assert!(parse("{host}").expect_err("host name").contains("host name"));
+    }
+}
@@ -0,0 +1,63 @@
+use rand_core::CryptoRngCore;
+use ssh_key::{Cipher, Kdf, PrivateKey};
+use std::time::{Duration, Instant};
+use zeroize::Zeroizing;
+
+use crate::error::KeyError;
+
+// OpenSSH's default number of bcrypt-pbkdf rounds, as used by ssh-keygen -a.
+pub const DEFAULT_KDF_ROUNDS: u32 = 16;
+
+// Salt length used by ssh-keygen and ssh-key.
+const SALT_SIZE: usize = 16;
+
+/** Passphrase protection for generated keys.  Keys are encrypted the way
+ * ssh-keygen does it: aes256-ctr with a key derived by bcrypt-pbkdf, whose
+ * round count dominates the cost of both encryption and decryption.
+ */
+#[derive(Clone, Debug)]
+pub struct EncryptOptions {
+    pub passphrase: Zeroizing<String>,
+    pub kdf_rounds: u32,
+}
+
+impl EncryptOptions {
+    pub fn new(passphrase: impl Into<String>, kdf_rounds: u32) -> EncryptOptions {
+        EncryptOptions {passphrase: Zeroizing::new(passphrase.into()), kdf_rounds}
+    }
+
+    pub fn cipher(&self) -> Cipher {
+        Cipher::Aes256Ctr
+    }
+
+    // Encrypt the key with a fresh random salt and checkint.  The comment is
+    // part of the encrypted data, so it is copied back onto the encrypted key
+    // for its public key encoding.
+    pub fn encrypt(&self, key: &PrivateKey, rng: &mut impl CryptoRngCore) -> Result<PrivateKey, KeyError> {
+        let mut salt = vec![0u8; SALT_SIZE];
+        rng.fill_bytes(&mut salt);
+        let kdf = Kdf::Bcrypt {salt, rounds: self.kdf_rounds};
+        let mut encrypted = key.encrypt_with(self.cipher(), kdf, rng.next_u32(), self.passphrase.as_bytes())
+            .map_err(KeyError::Encrypt)?;
+        encrypted.set_comment(key.comment());
+        Ok(encrypted)
+    }
+
+    pub fn decrypt(&self, key: &PrivateKey) -> Result<PrivateKey, KeyError> {
+        key.decrypt(self.passphrase.as_bytes()).map_err(KeyError::Decrypt)
+    }
+
+    // Encrypt and then decrypt the key, timing each step separately.
+    pub fn time_round_trip(&self, key: &PrivateKey, rng: &mut impl CryptoRngCore)
+                           -> Result<(PrivateKey, Duration, Duration), KeyError> {
+        let start = Instant::now();
+        let encrypted = self.encrypt(key, rng)?;
+        let encrypt_time = start.elapsed();
+
+        let start = Instant::now();
+        self.decrypt(&encrypted)?;
+        let decrypt_time = start.elapsed();
+
+        Ok((encrypted, encrypt_time, decrypt_time))
+    }
+}
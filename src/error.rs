@@ -0,0 +1,134 @@
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fmt, io};
+
+/** The ways generating and encoding a key can fail.  Each variant records the
+ * stage that failed along with the underlying ssh-key error, except Mismatch,
+ * which names what didn't survive a round trip intact, and Export, which
+ * records the kind of I/O error writing a key file failed with.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum KeyError {
+    Generate(ssh_key::Error),
+    EncodePrivate(ssh_key::Error),
+    EncodeBytes(ssh_key::Error),
+    EncodePublic(ssh_key::Error),
+    DecodePrivate(ssh_key::Error),
+    DecodeBytes(ssh_key::Error),
+    DecodePublic(ssh_key::Error),
+    Encrypt(ssh_key::Error),
+    Decrypt(ssh_key::Error),
+    Issue(ssh_key::Error),
+    Validate(ssh_key::Error),
+    Sign(ssh_key::Error),
+    Verify(ssh_key::Error),
+    Mismatch(&'static str),
+    Export(io::ErrorKind),
+}
+
+impl KeyError {
+    pub fn stage(&self) -> &'static str {
+        match self {
+            KeyError::Generate(_) => "generate",
+            KeyError::EncodePrivate(_) => "encode private key",
+            KeyError::EncodeBytes(_) => "encode private key bytes",
+            KeyError::EncodePublic(_) => "encode public key",
+            KeyError::DecodePrivate(_) => "decode private key",
+            KeyError::DecodeBytes(_) => "decode private key bytes",
+            KeyError::DecodePublic(_) => "decode public key",
+            KeyError::Encrypt(_) => "encrypt private key",
+            KeyError::Decrypt(_) => "decrypt private key",
+            KeyError::Issue(_) => "issue certificate",
+            KeyError::Validate(_) => "validate certificate",
+            KeyError::Sign(_) => "sign message",
+            KeyError::Verify(_) => "verify signature",
+            KeyError::Mismatch(_) => "verify round trip",
+            KeyError::Export(_) => "write key file",
+        }
+    }
+
+    pub fn source_error(&self) -> Option<&ssh_key::Error> {
+        let e = match self {
+            KeyError::Generate(e) | KeyError::EncodePrivate(e) | KeyError::EncodeBytes(e) |
+            KeyError::EncodePublic(e) | KeyError::DecodePrivate(e) | KeyError::DecodeBytes(e) |
+            KeyError::DecodePublic(e) | KeyError::Encrypt(e) | KeyError::Decrypt(e) |
+            KeyError::Issue(e) | KeyError::Validate(e) | KeyError::Sign(e) | KeyError::Verify(e) => e,
+            KeyError::Mismatch(_) | KeyError::Export(_) => return None,
+        };
+        Some(e)
+    }
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self, self.source_error()) {
+            (KeyError::Mismatch(what), _) => write!(f, "{}: {} doesn't match the original", self.stage(), what),
+            (KeyError::Export(kind), _) => write!(f, "{}: {}", self.stage(), kind),
+            (_, Some(e)) => write!(f, "{}: {}", self.stage(), e),
+            (_, None) => f.write_str(self.stage()),
+        }
+    }
+}
+
+impl std::error::Error for KeyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source_error().map(|e| e as _)
+    }
+}
+
+/** Failure accounting for a set of keys.  Errors are counted by category (the
+ * error's Display text, which names the stage and the cause), retries counts
+ * the extra generation attempts made and failed_keys the keys that still
+ * couldn't be generated once the retries ran out.  dropped_samples counts the
+ * times a key's timings for a step (verification, encoding, signing,
+ * certificates or encryption) were discarded because the step failed, so
+ * that step's latencies cover fewer keys than were generated.
+ */
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Failures {
+    pub errors: BTreeMap<String, u64>,
+    pub retries: u64,
+    pub failed_keys: u64,
+    #[serde(default)]
+    pub dropped_samples: u64,
+}
+
+impl Failures {
+    pub fn record(&mut self, error: &KeyError) {
+        *self.errors.entry(error.to_string()).or_insert(0) += 1;
+    }
+
+    // Record an error that cost the key its timings for the failed step.
+    pub fn record_dropped(&mut self, error: &KeyError) {
+        self.record(error);
+        self.dropped_samples += 1;
+    }
+
+    pub fn merge(&mut self, other: &Failures) {
+        for (category, count) in &other.errors {
+            *self.errors.entry(category.clone()).or_insert(0) += count;
+        }
+        self.retries += other.retries;
+        self.failed_keys += other.failed_keys;
+        self.dropped_samples += other.dropped_samples;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty() && self.retries == 0 && self.failed_keys == 0 && self.dropped_samples == 0
+    }
+
+    pub fn total_errors(&self) -> u64 {
+        self.errors.values().sum()
+    }
+
+    // Print the counts as an indented block, one line per error category.
+    pub fn print(&self) {
+        println!("  failed keys: {}", self.failed_keys);
+        println!("  retries:     {}", self.retries);
+        if self.dropped_samples > 0 {
+            println!("  dropped:     {} keys' samples for a failed step", self.dropped_samples);
+        }
+        for (category, count) in &self.errors {
+            println!("  {:>6} x {}", count, category);
+        }
+    }
+}
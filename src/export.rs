@@ -0,0 +1,82 @@
+use ssh_key::{LineEnding, PrivateKey};
+use std::{fs, io::{self, Write}, path::{Path, PathBuf}};
+
+use crate::alg::KeySpec;
+use crate::error::KeyError;
+
+/** Writing generated keys to disk the way ssh-keygen does: the private key
+ * to id_<spec> with mode 0600 and the public key to id_<spec>.pub with mode
+ * 0644, both carrying the key's comment.  The first count keys of each spec are
+ * written; the first key gets the plain name and later keys a _<n> suffix,
+ * e.g. id_ed25519, id_ed25519_2.  Existing files are only replaced when
+ * force is set.
+ */
+#[derive(Clone, Debug)]
+pub struct ExportOptions {
+    pub dir: PathBuf,
+    pub count: u32,
+    pub force: bool,
+}
+
+impl ExportOptions {
+    // The private and public key paths for the index'th key (from 1) of a spec.
+    pub fn paths(&self, spec: &KeySpec, index: u32) -> (PathBuf, PathBuf) {
+        let name = match index {
+            1 => format!("id_{}", spec),
+            n => format!("id_{}_{}", spec, n),
+        };
+        (self.dir.join(&name), self.dir.join(name + ".pub"))
+    }
+
+    // The files that writing keys for these specs would overwrite.
+    pub fn existing(&self, specs: &[KeySpec], keys_per_spec: impl Fn(&KeySpec) -> u32) -> Vec<PathBuf> {
+        specs.iter()
+            .flat_map(|spec| (1..=self.count.min(keys_per_spec(spec))).map(move |i| self.paths(spec, i)))
+            .flat_map(|(private, public)| [private, public])
+            .filter(|path| path.exists())
+            .collect()
+    }
+
+    // Write the key pair as the index'th key of the spec.
+    pub fn write(&self, spec: &KeySpec, index: u32, key: &PrivateKey) -> Result<(), KeyError> {
+        let private_pem = key.to_openssh(LineEnding::LF).map_err(KeyError::EncodePrivate)?;
+        let public_line = key.public_key().to_openssh().map_err(KeyError::EncodePublic)?;
+        let (private, public) = self.paths(spec, index);
+
+        create_dir(&self.dir).map_err(|e| KeyError::Export(e.kind()))?;
+        write_file(&private, private_pem.as_bytes(), 0o600, self.force)
+            .and_then(|_| write_file(&public, (public_line + "\n").as_bytes(), 0o644, self.force))
+            .map_err(|e| KeyError::Export(e.kind()))
+    }
+}
+
+// Create the key directory, private to its owner like ~/.ssh.
+pub fn create_dir(dir: &Path) -> io::Result<()> {
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder.create(dir)
+}
+
+// Write the file with the given permissions, failing if it already exists
+// unless force is set.  Forced writes reset the permissions of existing files.
+pub fn write_file(path: &Path, contents: &[u8], mode: u32, force: bool) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, mode);
+
+    let mut file = options.open(path)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(mode))?;
+    }
+    file.write_all(contents)
+}
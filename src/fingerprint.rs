@@ -0,0 +1,174 @@
+use md5::{Digest, Md5};
+use ssh_key::{public::KeyData, HashAlg, PublicKey};
+
+use crate::error::KeyError;
+use std::{fmt, str::FromStr};
+
+/** Fingerprint hashes, matching ssh-keygen -E.  SHA-256 and SHA-512
+ * fingerprints are base64 encoded ("SHA256:..."), MD5 fingerprints are the
+ * legacy colon separated hex ("MD5:12:f8:...") still expected by old tooling.
+ */
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FingerprintHash {
+    #[default]
+    Sha256,
+    Sha512,
+    Md5,
+}
+
+impl FingerprintHash {
+    pub const ALL: [FingerprintHash; 3] = [FingerprintHash::Sha256, FingerprintHash::Sha512, FingerprintHash::Md5];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FingerprintHash::Sha256 => "sha256",
+            FingerprintHash::Sha512 => "sha512",
+            FingerprintHash::Md5 => "md5",
+        }
+    }
+
+    // The digest of the key's wire encoding, which MD5 has to encode itself.
+    fn digest(self, key: &PublicKey) -> Result<Vec<u8>, KeyError> {
+        Ok(match self {
+            FingerprintHash::Sha256 => key.fingerprint(HashAlg::Sha256).as_bytes().to_vec(),
+            FingerprintHash::Sha512 => key.fingerprint(HashAlg::Sha512).as_bytes().to_vec(),
+            FingerprintHash::Md5 => Md5::digest(key.to_bytes().map_err(KeyError::EncodePublic)?).to_vec(),
+        })
+    }
+
+    // The label ssh-keygen puts below the randomart box.
+    fn footer(self) -> &'static str {
+        match self {
+            FingerprintHash::Sha256 => "[SHA256]",
+            FingerprintHash::Sha512 => "[SHA512]",
+            FingerprintHash::Md5 => "[MD5]",
+        }
+    }
+}
+
+impl fmt::Display for FingerprintHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for FingerprintHash {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FingerprintHash::ALL.into_iter()
+            .find(|h| h.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown fingerprint hash '{}' (valid options: sha256, sha512, md5)", s))
+    }
+}
+
+// The key's fingerprint as ssh-keygen -l -E <hash> prints it.
+pub fn fingerprint(key: &PublicKey, hash: FingerprintHash) -> Result<String, KeyError> {
+    Ok(match hash {
+        FingerprintHash::Sha256 => key.fingerprint(HashAlg::Sha256).to_string(),
+        FingerprintHash::Sha512 => key.fingerprint(HashAlg::Sha512).to_string(),
+        FingerprintHash::Md5 => {
+            let hex: Vec<_> = hash.digest(key)?.iter().map(|b| format!("{:02x}", b)).collect();
+            format!("MD5:{}", hex.join(":"))
+        }
+    })
+}
+
+// The fingerprint's "drunken bishop" visualization as ssh-keygen -lv draws it,
+// e.g. with a "[ED25519 256]" header and a "[SHA256]" footer.
+pub fn randomart(key: &PublicKey, hash: FingerprintHash) -> Result<String, KeyError> {
+    const WIDTH: usize = 17;
+    const HEIGHT: usize = 9;
+    const SYMBOLS: &[u8] = b" .o+=*BOX@%&#/^SE";
+    let start_mark = SYMBOLS.len() - 2;
+    let end_mark = SYMBOLS.len() - 1;
+
+    // The bishop starts in the centre and makes four moves per byte, two bits
+    // at a time, counting visits to each square.
+    let mut field = [[0usize; WIDTH]; HEIGHT];
+    let (mut x, mut y) = (WIDTH / 2, HEIGHT / 2);
+    for byte in hash.digest(key)? {
+        for step in 0..4 {
+            let bits = byte >> (step * 2);
+            x = if bits & 1 == 0 {x.saturating_sub(1)} else {(x + 1).min(WIDTH - 1)};
+            y = if bits & 2 == 0 {y.saturating_sub(1)} else {(y + 1).min(HEIGHT - 1)};
+            if field[y][x] < start_mark - 1 {
+                field[y][x] += 1;
+            }
+        }
+    }
+    field[HEIGHT / 2][WIDTH / 2] = start_mark;
+    field[y][x] = end_mark;
+
+    let header = format!("[{} {}]", key_type(key.key_data()), key_bits(key.key_data()));
+    let mut art = format!("+{:-^width$}+\n", header, width = WIDTH);
+    for row in field {
+        let line: String = row.iter().map(|&v| SYMBOLS[v] as char).collect();
+        art += &format!("|{}|\n", line);
+    }
+    Ok(art + &format!("+{:-^width$}+", hash.footer(), width = WIDTH))
+}
+
+// The key type as ssh-keygen names it in randomart headers.
+fn key_type(key: &KeyData) -> &'static str {
+    match key {
+        KeyData::Rsa(_) => "RSA",
+        KeyData::Ecdsa(_) => "ECDSA",
+        KeyData::Ed25519(_) => "ED25519",
+        _ => "UNKNOWN",
+    }
+}
+
+pub fn key_bits(key: &KeyData) -> u32 {
+    match key {
+        KeyData::Rsa(rsa) => rsa::BigUint::try_from(&rsa.n).map(|n| n.bits() as u32).unwrap_or(0),
+        KeyData::Ecdsa(ecdsa) => crate::alg::curve_size(ecdsa.curve()),
+        KeyData::Ed25519(_) => 256,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A public key and its fingerprints as printed by ssh-keygen -l -E.
+    const KEY: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIP4V/7h7hnUHMPZot7PeOGaTQJuLozP3uT/7Dca6sGco user@vm";
+
+    #[test]
+    fn fingerprints_match_ssh_keygen() {
+        let key = PublicKey::from_openssh(KEY).expect("test key");
+        assert_eq!(fingerprint(&key, FingerprintHash::Md5).expect("fingerprint"), "MD5:2c:8f:b8:eb:a2:f6:57:9b:51:93:a9:56:05:72:68:c1");
+        assert_eq!(fingerprint(&key, FingerprintHash::Sha256).expect("fingerprint"), "SHA256:pUZHlmnZ13a/ZOzYLq1V2oL7P+lmGM9pJZWG98F43gg");
+    }
+
+    // The same key's randomart as printed by ssh-keygen -lv -E.
+    #[test]
+    fn randomart_matches_ssh_keygen() {
+        let key = PublicKey::from_openssh(KEY).expect("test key");
+        assert_eq!(randomart(&key, FingerprintHash::Sha256).expect("randomart"), "\
++--[ED25519 256]--+
+|          o=   . |
+|         o= . . +|
+|        ..o  .=.+|
+|       . +  Eo @o|
+|        S    .@o*|
+|       .     +oB=|
+|            . Xo=|
+|             +.& |
+|            .oOoo|
++----[SHA256]-----+");
+        assert_eq!(randomart(&key, FingerprintHash::Md5).expect("randomart"), "\
++--[ED25519 256]--+
+|      .ooo.      |
+|       Eo  .     |
+|      .   +      |
+|       . *       |
+|      . S .      |
+|     . O         |
+|    . + =        |
+| ..  o o         |
+|o..+=.           |
++------[MD5]------+");
+    }
+}
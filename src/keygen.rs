@@ -0,0 +1,369 @@
+use rand_core::CryptoRngCore; // rand is implicitly exposed
+use ssh_key::{Certificate, Fingerprint, HashAlg, LineEnding, PrivateKey};
+use std::{collections::BTreeMap, ops::Deref, time::{Duration, Instant}};
+
+use crate::alg::KeySpec;
+use crate::cert::{CertIssuer, OP_ISSUE, OP_VALIDATE};
+use crate::codec;
+use crate::comment::CommentTemplate;
+use crate::encrypt::EncryptOptions;
+use crate::error::{Failures, KeyError};
+use crate::export::ExportOptions;
+use crate::fingerprint::{self, FingerprintHash};
+use crate::sign::SignOptions;
+use crate::verify;
+
+/** A freshly generated key along with how long it took to generate.  This is
+ * the entry point for services that just need a key: generate one for a spec
+ * and hand out its OpenSSH encodings and fingerprint.
+ *
+ * ```no_run
+ * # use sshkeytest::GeneratedKey;
+ * # fn main() -> Result<(), Box<dyn std::error::Error>> {
+ * let mut rng = rand::rngs::OsRng;
+ * let key = GeneratedKey::generate(&"ecdsa-p256".parse()?, &mut rng)?;
+ * let public = key.public_openssh()?;
+ * # Ok(())
+ * # }
+ * ```
+ */
+#[derive(Clone, Debug)]
+pub struct GeneratedKey {
+    pub private_key: PrivateKey,
+    pub elapsed: Duration,
+}
+
+impl GeneratedKey {
+    pub fn generate(spec: &KeySpec, rng: &mut impl CryptoRngCore) -> Result<GeneratedKey, KeyError> {
+        let start = Instant::now();
+        let private_key = spec.generate(rng).map_err(KeyError::Generate)?;
+        Ok(GeneratedKey {private_key, elapsed: start.elapsed()})
+    }
+
+    // The OpenSSH PEM encoding of the private key.  The returned string is
+    // zeroized when dropped.
+    pub fn private_openssh(&self) -> Result<zeroize::Zeroizing<String>, KeyError> {
+        self.private_key.to_openssh(LineEnding::LF).map_err(KeyError::EncodePrivate)
+    }
+
+    // The authorized_keys style public key line.
+    pub fn public_openssh(&self) -> Result<String, KeyError> {
+        self.private_key.public_key().to_openssh().map_err(KeyError::EncodePublic)
+    }
+
+    pub fn fingerprint(&self, hash_alg: HashAlg) -> Fingerprint {
+        self.private_key.fingerprint(hash_alg)
+    }
+}
+
+/** What gen_ssh_keys() does with each key besides generating it.
+ *
+ *  print_first  - print the first key's encodings and fingerprint.
+ *  fingerprint  - the hash used for the printed fingerprint.
+ *  randomart    - also print the fingerprint's randomart, as ssh-keygen -lv does.
+ *  comment      - the template for each key's comment; keys have no comment without one.
+ *  first_index  - the number of keys generated before this call, added to
+ *                 each key's index so indexes stay unique across threads and calls.
+ *  export       - write the keys with the first indexes to files, encrypted if
+ *                 encrypt is set; a key that fails to encrypt is never written.
+ *  collect_keys - keep every key, as generated and commented but unencrypted, e.g.
+ *                 for an authorized_keys file or a key pool.
+ *  defer_output - neither export nor print keys, but return what would have been
+ *                 written in the run's outputs, for callers that only decide
+ *                 afterwards which keys to keep.
+ *  verify       - check each key survives a round trip through its encodings,
+ *                 dumping any key that doesn't to stderr and otherwise skipping it.
+ *  retries      - how many times to retry a failed generation.
+ *  encrypt      - passphrase protect each key, timing encryption and decryption.
+ *  cert         - issue a certificate for each key, timing issuance and validation.
+ *  sign         - sign and verify messages with each key, timing each step.
+ *  serialize    - encode each key in every format and parse it back, timing each step.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct GenOptions {
+    pub print_first: bool,
+    pub fingerprint: FingerprintHash,
+    pub randomart: bool,
+    pub comment: Option<CommentTemplate>,
+    pub first_index: u32,
+    pub export: Option<ExportOptions>,
+    pub collect_keys: bool,
+    pub defer_output: bool,
+    pub retries: u32,
+    pub verify: bool,
+    pub encrypt: Option<EncryptOptions>,
+    pub cert: Option<CertIssuer>,
+    pub sign: Option<SignOptions>,
+    pub serialize: bool,
+}
+
+/** The keys generated by one call to gen_ssh_keys(): the time each key took to
+ * generate (and encrypt and decrypt, if requested), the time taken by any
+ * other per-key operations keyed by operation name, the keys themselves if
+ * they were collected, their deferred output, an account of everything that
+ * went wrong along the way and the last error that cost a key, if any.
+ */
+#[derive(Clone, Debug, Default)]
+pub struct KeyRun {
+    pub samples: Vec<Duration>,
+    pub encrypt_samples: Vec<Duration>,
+    pub decrypt_samples: Vec<Duration>,
+    pub ops: BTreeMap<String, Vec<Duration>>,
+    pub keys: Vec<PrivateKey>,
+    pub outputs: Vec<KeyOutput>,
+    pub failures: Failures,
+    pub error: Option<KeyError>,
+}
+
+/** What gen_ssh_keys() writes and prints for a key: the key, encrypted if
+ * requested, and its certificate, if one was issued.
+ */
+#[derive(Clone, Debug)]
+pub struct KeyOutput {
+    pub key: PrivateKey,
+    pub cert: Option<Certificate>,
+}
+
+impl KeyOutput {
+    // Export the key as the index'th of the spec, if options export that
+    // many, and print it if print is set, recording any failures.
+    pub fn write(&self, spec: &KeySpec, index: u32, print: bool, options: &GenOptions, failures: &mut Failures) {
+        if let Some(export) = options.export.as_ref().filter(|e| index <= e.count) {
+            if let Err(e) = export.write(spec, index, &self.key) {
+                failures.record(&e);
+            }
+        }
+
+        if print {
+            for e in print_key(&self.key, options.fingerprint, options.randomart) {
+                failures.record(&e);
+            }
+            if let Some(cert) = &self.cert {
+                match cert.to_openssh() {
+                    Ok(c) => println!("\n------- Certificate: \n{}", c),
+                    Err(e) => failures.record(&KeyError::Issue(e)),
+                }
+            }
+        }
+    }
+}
+
+impl KeyRun {
+    // Add another run's keys, samples and failures to this one.
+    pub fn append(&mut self, other: KeyRun) {
+        self.samples.extend(other.samples);
+        self.encrypt_samples.extend(other.encrypt_samples);
+        self.decrypt_samples.extend(other.decrypt_samples);
+        for (op, samples) in other.ops {
+            self.ops.entry(op).or_default().extend(samples);
+        }
+        self.keys.extend(other.keys);
+        self.outputs.extend(other.outputs);
+        self.failures.merge(&other.failures);
+        if other.error.is_some() {
+            self.error = other.error;
+        }
+    }
+
+    pub fn record_op(&mut self, op: &str, elapsed: Duration) {
+        self.ops.entry(op.to_string()).or_default().push(elapsed);
+    }
+}
+
+// Generate the keys and return the time each individual key took to generate.
+// A failed generation is retried up to retries times; the time recorded for a
+// retried key includes its failed attempts, since that is what a caller waits.
+pub fn gen_ssh_keys(iterations: u32, rng: &mut impl CryptoRngCore, spec: &KeySpec,
+                    options: &GenOptions) -> KeyRun {
+    // Create keys in a loop, timing each one.
+    let mut run = KeyRun {samples: Vec::with_capacity(iterations as usize), ..Default::default()};
+    let mut key_cnt = 0;
+    while key_cnt < iterations {
+        // Increment key counter.
+        key_cnt += 1;
+
+        let start = Instant::now();
+        let mut attempt = 0;
+        let key = loop {
+            match GeneratedKey::generate(spec, rng) {
+                Ok(k) => break Some(k),
+                Err(e) => {
+                    run.failures.record(&e);
+                    if attempt == options.retries {
+                        run.error = Some(e);
+                        break None;
+                    }
+                    attempt += 1;
+                    run.failures.retries += 1;
+                }
+            }
+        };
+        let Some(mut key) = key else {
+            run.failures.failed_keys += 1;
+            continue;
+        };
+        run.samples.push(start.elapsed());
+
+        // Comment the key before it is encrypted, since the comment is part
+        // of the encrypted data.
+        let index = options.first_index + key_cnt;
+        if let Some(comment) = &options.comment {
+            key.private_key.set_comment(comment.render(spec, index));
+        }
+
+        // Check the key survives a round trip through its encodings.  A key
+        // that doesn't is neither collected, written nor printed, and its
+        // private half is only dumped if it wasn't meant to be encrypted.
+        if options.verify {
+            if let Err(e) = verify::verify_round_trip(&key.private_key) {
+                verify::dump_key(&key.private_key, &e, options.encrypt.is_none());
+                run.failures.record_dropped(&e);
+                run.error = Some(e);
+                continue;
+            }
+        }
+
+        // Encode the key in every format and parse it back.
+        if options.serialize {
+            match codec::time_encodings(&key.private_key) {
+                Ok(timings) => {
+                    for (op, elapsed) in timings {
+                        run.record_op(op, elapsed);
+                    }
+                }
+                Err(e) => run.failures.record_dropped(&e),
+            }
+        }
+
+        // Sign and verify messages with the key.
+        if let Some(sign) = &options.sign {
+            match sign.time_sign_verify(&key.private_key) {
+                Ok(timings) => {
+                    for (op, elapsed) in timings {
+                        run.record_op(&op, elapsed);
+                    }
+                }
+                Err(e) => run.failures.record_dropped(&e),
+            }
+        }
+
+        // Issue a certificate for the key.  The serial and nonce come from
+        // OsRng rather than the key RNG, so that a seeded run generates the
+        // same keys with or without --cert.
+        let mut cert = None;
+        if let Some(issuer) = &options.cert {
+            match issuer.time_issue_and_validate(key.private_key.public_key(), &mut rand::rngs::OsRng) {
+                Ok((c, issue_time, validate_time)) => {
+                    run.record_op(OP_ISSUE, issue_time);
+                    run.record_op(OP_VALIDATE, validate_time);
+                    cert = Some(c);
+                }
+                Err(e) => run.failures.record_dropped(&e),
+            }
+        }
+
+        // Passphrase protect the key, keeping the encrypted key for printing.
+        // A key that couldn't be encrypted is neither collected, written nor
+        // printed, rather than falling back to the unencrypted key.  As for
+        // certificates, the salt and checkint come from OsRng so that a
+        // seeded run generates the same keys with or without a passphrase.
+        let mut encrypted = None;
+        if let Some(encrypt) = &options.encrypt {
+            match encrypt.time_round_trip(&key.private_key, &mut rand::rngs::OsRng) {
+                Ok((k, encrypt_time, decrypt_time)) => {
+                    run.encrypt_samples.push(encrypt_time);
+                    run.decrypt_samples.push(decrypt_time);
+                    encrypted = Some(k);
+                }
+                Err(e) => {
+                    run.failures.record_dropped(&e);
+                    run.error = Some(e);
+                    continue;
+                }
+            }
+        }
+
+        // Collect the unencrypted key, now that it is going to be written.
+        if options.collect_keys {
+            run.keys.push(key.private_key.clone());
+        }
+
+        // Write the key to disk and print the first key.
+        let output = KeyOutput {key: encrypted.unwrap_or(key.private_key), cert};
+        if options.defer_output {
+            run.outputs.push(output);
+        } else {
+            output.write(spec, index, key_cnt == 1 && options.print_first, options, &mut run.failures);
+        }
+    }
+
+    run
+}
+
+// Print the key's private and public encodings and its fingerprint, optionally
+// with randomart, to stdout, returning any encoding errors.
+pub fn print_key(key: &PrivateKey, hash: FingerprintHash, randomart: bool) -> Vec<KeyError> {
+    let mut errors = Vec::new();
+
+    println!("------- Private key:");
+    match key.to_openssh(LineEnding::LF) {
+        Ok(k) => println!("{}", k.deref()),
+        Err(e) => errors.push(KeyError::EncodePrivate(e)),
+    }
+
+    match key.to_bytes() {
+        Ok(b) => println!("Key length in bytes = {}", b.deref().len()),
+        Err(e) => errors.push(KeyError::EncodeBytes(e)),
+    }
+
+    match key.public_key().to_openssh() {
+        Ok(pubk) => println!("\n------- Public key: \n{}", pubk),
+        Err(e) => errors.push(KeyError::EncodePublic(e)),
+    }
+
+    match fingerprint::fingerprint(key.public_key(), hash) {
+        Ok(fp) => println!("\n------- fingerprint: \n{}", fp),
+        Err(e) => errors.push(e),
+    }
+    if randomart {
+        match fingerprint::randomart(key.public_key(), hash) {
+            Ok(art) => println!("{}", art),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    for e in &errors {
+        println!("Unable to {}", e);
+    }
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cert::CertOptions;
+    use rand_chacha::{rand_core::SeedableRng, ChaCha8Rng};
+    use ssh_key::certificate::CertType;
+
+    fn seeded_keys(spec: &KeySpec, options: &GenOptions) -> Vec<PrivateKey> {
+        let options = GenOptions {collect_keys: true, ..options.clone()};
+        gen_ssh_keys(3, &mut ChaCha8Rng::seed_from_u64(7), spec, &options).keys
+    }
+
+    #[test]
+    fn certificates_dont_change_seeded_keys() {
+        let spec: KeySpec = "ed25519".parse().expect("spec");
+        let options = CertOptions {cert_type: CertType::User, key_id: "test".into(), principals: vec!["alice".into()],
+                                   validity: Duration::from_secs(60), critical_options: vec![], extensions: vec![]};
+        let issuer = CertIssuer::generate(&spec, options, &mut ChaCha8Rng::seed_from_u64(1)).expect("CA");
+        let with_cert = GenOptions {cert: Some(issuer), ..GenOptions::default()};
+        assert_eq!(seeded_keys(&spec, &GenOptions::default()), seeded_keys(&spec, &with_cert));
+    }
+
+    #[test]
+    fn encryption_doesnt_change_seeded_keys() {
+        let spec: KeySpec = "ed25519".parse().expect("spec");
+        let with_passphrase = GenOptions {encrypt: Some(EncryptOptions::new("x", 1)), ..GenOptions::default()};
+        assert_eq!(seeded_keys(&spec, &GenOptions::default()), seeded_keys(&spec, &with_passphrase));
+    }
+}
@@ -0,0 +1,255 @@
+use hmac::{Hmac, Mac};
+use rand_core::CryptoRngCore;
+use sha1::Sha1;
+use ssh_key::{known_hosts::{HostPatterns, Marker}, KnownHosts, LineEnding, PrivateKey, PublicKey};
+use std::{fs, io, path::{Path, PathBuf}};
+
+use crate::alg::KeySpec;
+use crate::cert::CertIssuer;
+use crate::export::{self, write_file};
+
+// The salt length ssh-keygen -H uses, the same as the SHA-1 digest length.
+const SALT_SIZE: usize = 20;
+
+/** One line of a known_hosts file: an optional marker, the host patterns the
+ * line applies to (or a single hashed hostname) and the public key.
+ */
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HostEntry {
+    pub marker: Option<Marker>,
+    pub patterns: HostPatterns,
+    pub public_key: PublicKey,
+}
+
+impl HostEntry {
+    pub fn host(host: &str, public_key: PublicKey) -> HostEntry {
+        HostEntry {marker: None, patterns: HostPatterns::Patterns(vec![host.to_string()]), public_key}
+    }
+
+    // A host key entry with the hostname hashed with a random salt.  The key's
+    // comment is dropped, since it usually names the host.
+    pub fn hashed_host(host: &str, mut public_key: PublicKey, rng: &mut impl CryptoRngCore) -> HostEntry {
+        public_key.set_comment("");
+        HostEntry {marker: None, patterns: hash_host(host, rng), public_key}
+    }
+
+    // An @cert-authority entry trusting the CA's certificates for hosts
+    // matching the pattern.  Patterns can't be hashed.
+    pub fn cert_authority(pattern: &str, ca: PublicKey) -> HostEntry {
+        let patterns = HostPatterns::Patterns(pattern.split(',').map(str::to_string).collect());
+        HostEntry {marker: Some(Marker::CertAuthority), patterns, public_key: ca}
+    }
+
+    pub fn to_line(&self) -> Result<String, ssh_key::Error> {
+        let marker = self.marker.as_ref().map(|m| format!("{} ", m)).unwrap_or_default();
+        Ok(format!("{}{} {}", marker, self.patterns.to_string(), self.public_key.to_openssh()?))
+    }
+}
+
+// Hash a hostname the way ssh-keygen -H does: |1|base64(salt)|base64(HMAC-SHA1(salt, host)).
+pub fn hash_host(host: &str, rng: &mut impl CryptoRngCore) -> HostPatterns {
+    let mut salt = vec![0u8; SALT_SIZE];
+    rng.fill_bytes(&mut salt);
+    let hash = hmac_sha1(&salt, host.as_bytes());
+    HostPatterns::HashedName {salt, hash}
+}
+
+// Whether a host matches the patterns, the way ssh looks a host up: a hashed
+// name must hash to the same value with its salt, and a pattern list must have
+// a matching pattern and no matching negated pattern.
+pub fn matches(patterns: &HostPatterns, host: &str) -> bool {
+    match patterns {
+        HostPatterns::HashedName {salt, hash} => hmac_sha1(salt, host.as_bytes()) == *hash,
+        HostPatterns::Patterns(list) => {
+            let mut matched = false;
+            for pattern in list {
+                match pattern.strip_prefix('!') {
+                    Some(negated) if glob(negated, host) => return false,
+                    Some(_) => (),
+                    None => matched |= glob(pattern, host),
+                }
+            }
+            matched
+        }
+    }
+}
+
+// Case insensitive matching with * and ? wildcards, as in ssh_config(5) patterns.
+fn glob(pattern: &str, host: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let host = host.to_ascii_lowercase();
+    let (p, h) = (pattern.as_bytes(), host.as_bytes());
+    let (mut pi, mut hi) = (0, 0);
+    let mut star = None;
+    while hi < h.len() {
+        if pi < p.len() && (p[pi] == b'?' || p[pi] == h[hi]) {
+            pi += 1;
+            hi += 1;
+        } else if pi < p.len() && p[pi] == b'*' {
+            star = Some((pi, hi));
+            pi += 1;
+        } else if let Some((sp, sh)) = star {
+            pi = sp + 1;
+            hi = sh + 1;
+            star = Some((sp, sh + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == b'*')
+}
+
+fn hmac_sha1(key: &[u8], data: &[u8]) -> [u8; 20] {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC takes keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+// Reject a hostname that would corrupt its known_hosts line: one that is
+// empty or has whitespace or control characters, which end the host field,
+// a comma, which separates patterns, or a '#', which starts a comment.
+pub fn check_host(host: &str) -> Result<(), String> {
+    if host.is_empty() || host.contains(|c: char| c.is_whitespace() || c.is_control() || c == ',' || c == '#') {
+        return Err(format!("invalid host '{}': it must be non-empty without spaces, commas or '#'",
+                           host.escape_debug()));
+    }
+    Ok(())
+}
+
+pub fn render(entries: &[HostEntry]) -> Result<String, ssh_key::Error> {
+    let mut contents = String::new();
+    for entry in entries {
+        contents += &entry.to_line()?;
+        contents.push('\n');
+    }
+    Ok(contents)
+}
+
+// Parse the contents back with ssh-key, check every entry is as written and
+// that looking each host up finds its host keys.
+pub fn verify(contents: &str, entries: &[HostEntry], hosts: &[String]) -> Result<(), String> {
+    let parsed: Vec<_> = KnownHosts::new(contents).collect::<Result<_, _>>()
+        .map_err(|e| format!("unable to parse known_hosts: {}", e))?;
+    if parsed.len() != entries.len() {
+        return Err(format!("found {} entries, expected {}", parsed.len(), entries.len()));
+    }
+    for (i, (got, expected)) in parsed.iter().zip(entries).enumerate() {
+        if got.marker() != expected.marker.as_ref() || *got.host_patterns() != expected.patterns ||
+           *got.public_key() != expected.public_key {
+            return Err(format!("entry {} doesn't match what was written", i + 1));
+        }
+    }
+
+    for host in hosts {
+        if !parsed.iter().any(|p| p.marker().is_none() && matches(p.host_patterns(), host)) {
+            return Err(format!("looking up {} doesn't find its host keys", host));
+        }
+    }
+    Ok(())
+}
+
+// Write the file, then read it back and verify it.  The contents are verified
+// before anything is written too, so entries that can't be parsed never reach
+// the disk.  An existing file is only replaced when force is set.
+pub fn write(path: &Path, entries: &[HostEntry], hosts: &[String], force: bool) -> io::Result<()> {
+    let contents = render(entries).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    verify(&contents, entries, hosts).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    write_file(path, contents.as_bytes(), 0o644, force)?;
+    let written = fs::read_to_string(path)?;
+    verify(&written, entries, hosts).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+// Write a host's private and public key as sshd expects them, plus a host
+// certificate if there is a CA, returning the files written.
+pub fn write_host_key(dir: &Path, host: &str, spec: &KeySpec, key: &PrivateKey, issuer: Option<&CertIssuer>,
+                      force: bool, rng: &mut impl CryptoRngCore) -> Result<Vec<PathBuf>, String> {
+    let name = format!("ssh_host_{}_key", spec);
+    let mut files = vec![
+        (dir.join(&name), key.to_openssh(LineEnding::LF).map(|k| k.to_string()), 0o600),
+        (dir.join(format!("{}.pub", name)), key.public_key().to_openssh(), 0o644),
+    ];
+    if let Some(issuer) = issuer {
+        let mut issuer = issuer.clone();
+        issuer.options.principals = vec![host_name(host)];
+        let cert = issuer.issue(key.public_key(), rng)
+            .and_then(|cert| issuer.validate(&cert).map(|_| cert))
+            .map_err(|e| format!("unable to certify {}: {}", host, e))?;
+        files.push((dir.join(format!("{}-cert.pub", name)), cert.to_openssh(), 0o644));
+    }
+
+    export::create_dir(dir).map_err(|e| format!("unable to create {}: {}", dir.display(), e))?;
+    let mut written = Vec::new();
+    for (path, contents, mode) in files {
+        let contents = contents.map_err(|e| format!("unable to encode {}: {}", path.display(), e))?;
+        export::write_file(&path, format!("{}\n", contents.trim_end()).as_bytes(), mode, force)
+            .map_err(|e| format!("unable to write {}: {}", path.display(), e))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+// The hostname without the brackets and port of a [host]:port entry.
+pub fn host_name(host: &str) -> String {
+    match host.strip_prefix('[').and_then(|h| h.split_once("]:")) {
+        Some((name, _)) => name.to_string(),
+        None => host.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_chacha::{rand_core::SeedableRng, ChaCha8Rng};
+
+    const KEY: &str = "ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIP4V/7h7hnUHMPZot7PeOGaTQJuLozP3uT/7Dca6sGco";
+
+    #[test]
+    fn hashed_entries_dont_reveal_the_host() {
+        let host = "[node2.cluster]:2222";
+        let mut key = PublicKey::from_openssh(KEY).expect("test key");
+        key.set_comment(host);
+        let entry = HostEntry::hashed_host(host, key, &mut ChaCha8Rng::seed_from_u64(1));
+        let line = entry.to_line().expect("encoded");
+        assert!(line.starts_with("|1|"), "{}", line);
+        assert!(!line.contains("node2"), "{}", line);
+        assert!(line.ends_with(KEY), "{}", line);
+
+        let entries = [entry];
+        let contents = render(&entries).expect("rendered");
+        assert!(!contents.contains("node2"), "{}", contents);
+        verify(&contents, &entries, &[host.to_string()]).expect("verified");
+    }
+
+    // The key above hashed for [node2.cluster]:2222 by ssh-keygen -H.
+    const HASHED: &str = "|1|btEKFbllCPayO0gQi9h7y5spnHU=|eNDiy4ro+2S4uU67CJUK8Cjr340= \
+                          ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAIP4V/7h7hnUHMPZot7PeOGaTQJuLozP3uT/7Dca6sGco";
+
+    #[test]
+    fn matches_hosts_hashed_by_ssh_keygen() {
+        let entry = KnownHosts::new(HASHED).next().expect("one entry").expect("parsed");
+        assert!(matches(entry.host_patterns(), "[node2.cluster]:2222"));
+        assert!(!matches(entry.host_patterns(), "node2.cluster"));
+    }
+
+    #[test]
+    fn rejects_hosts_that_would_corrupt_the_line() {
+        for host in ["node1.cluster", "[node2.cluster]:2222", "10.0.0.1"] {
+            check_host(host).expect("valid host");
+        }
+        for host in ["", "bad host", "a,b", "node#1", "node\n1"] {
+            check_host(host).expect_err("invalid host");
+        }
+    }
+
+    // A file that wouldn't parse back is never written.
+    #[test]
+    fn write_verifies_before_writing() {
+        let path = std::env::temp_dir().join(format!("sshkeytest-known-hosts-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let key = PublicKey::from_openssh(KEY).expect("test key");
+        let hosts = ["bad host".to_string()];
+        let entries = [HostEntry::host(&hosts[0], key)];
+        assert!(write(&path, &entries, &hosts, false).is_err());
+        assert!(!path.exists());
+    }
+}
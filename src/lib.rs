@@ -0,0 +1,63 @@
+//! SSH key generation and benchmarking on top of the ssh-key crate.
+//!
+//! The sshkeytest binary is a thin command-line wrapper around this library,
+//! so services can depend on the same code paths that are benchmarked:
+//!
+//! - `alg`       - what to generate (`KeySpec`: family, curve or size, hash).
+//! - `keygen`    - generating, encoding, fingerprinting and timing keys.
+//! - `codec`     - timed encoding and parsing of every key format.
+//! - `comment`   - comment templates for generated keys.
+//! - `authorized_keys` - authorized_keys files with per-key options.
+//! - `encrypt`   - passphrase protection with bcrypt-pbkdf.
+//! - `cert`      - issuing and validating OpenSSH certificates.
+//! - `sign`      - SSHSIG signing and verification with generated keys.
+//! - `verify`    - round trip correctness checks for generated keys.
+//! - `fingerprint` - SHA-256, SHA-512 and MD5 fingerprints and randomart.
+//! - `export`    - writing keys to id_* files with ssh-keygen's permissions.
+//! - `known_hosts` - known_hosts files with hashed hosts and CA entries.
+//! - `error`     - typed key errors and failure accounting.
+//! - `rng`       - selectable and seedable random number generators.
+//! - `parallel`  - multi-threaded generation and scaling curves.
+//! - `budget`    - runs bounded by time or by the confidence interval on the mean.
+//! - `pool`      - a pool of pre-generated keys with a background refill thread.
+//! - `server`    - an HTTP stand-in for the Security Kernel's key generation endpoint.
+//! - `load`      - a load-testing client for key generation endpoints.
+//! - `stats`, `report`, `baseline` - latency statistics, reports and
+//!   regression detection.
+//! - `chart`     - ASCII histograms and SVG latency charts.
+
+pub mod alg;
+pub mod authorized_keys;
+pub mod baseline;
+pub mod budget;
+pub mod cert;
+pub mod chart;
+pub mod codec;
+pub mod comment;
+pub mod encrypt;
+pub mod error;
+pub mod export;
+pub mod fingerprint;
+pub mod keygen;
+pub mod known_hosts;
+pub mod load;
+pub mod parallel;
+pub mod pool;
+pub mod report;
+pub mod rng;
+pub mod server;
+pub mod sign;
+pub mod verify;
+pub mod stats;
+
+#[cfg(test)]
+mod test_keys;
+
+pub use alg::{KeyAlg, KeySpec};
+pub use error::{Failures, KeyError};
+pub use encrypt::EncryptOptions;
+pub use fingerprint::FingerprintHash;
+pub use keygen::{gen_ssh_keys, GenOptions, GeneratedKey, KeyRun};
+pub use pool::{KeyPool, PoolConfig, PoolStats};
+pub use rng::{RngConfig, RngKind};
+pub use sign::SignOptions;
@@ -0,0 +1,291 @@
+use std::collections::BTreeMap;
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, Instant};
+use std::{fmt, fs, io, path::Path, thread};
+
+use crate::alg::KeySpec;
+use crate::stats::Summary;
+
+// The most buckets a timeline is split into, however long the test ran.
+pub const MAX_INTERVALS: usize = 10_000;
+
+/** How requests are issued during a load test.
+ *
+ *  Rate        - open loop: requests start on a fixed schedule of this many
+ *                per second, whether or not earlier requests have finished.
+ *  Concurrency - closed loop: this many clients each send their next request
+ *                as soon as the previous one completes.
+ */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LoadMode {
+    Rate(f64),
+    Concurrency(usize),
+}
+
+impl fmt::Display for LoadMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadMode::Rate(rate) => write!(f, "{} requests/s", rate),
+            LoadMode::Concurrency(clients) => write!(f, "{} concurrent clients", clients),
+        }
+    }
+}
+
+/** A load test against a key generation endpoint: POST body to url for
+ * duration.  In rate mode at most max_in_flight requests are outstanding at
+ * once; later requests wait for a free client, and that wait counts towards
+ * their latency so an overloaded service isn't flattered.  Throughput and
+ * latency over time are reported in buckets of interval, widened if need be
+ * so that there are at most MAX_INTERVALS of them.
+ */
+#[derive(Clone, Debug)]
+pub struct LoadConfig {
+    pub url: String,
+    pub body: String,
+    pub mode: LoadMode,
+    pub duration: Duration,
+    pub max_in_flight: usize,
+    pub timeout: Duration,
+    pub interval: Duration,
+}
+
+impl LoadConfig {
+    // The JSON body asking a POST /keys endpoint for one key of the spec.
+    pub fn key_request(spec: &KeySpec, passphrase: Option<&str>) -> String {
+        let mut body = serde_json::json!({"algorithm": spec.family.name(), "size": spec.key_size});
+        if let Some(passphrase) = passphrase {
+            body["passphrase"] = passphrase.into();
+        }
+        body.to_string()
+    }
+}
+
+// One finished request: when it completed relative to the start of the test,
+// how long it took and, if it failed, the error category.
+#[derive(Clone, Debug)]
+pub struct Completion {
+    pub at: Duration,
+    pub latency: Duration,
+    pub error: Option<String>,
+}
+
+// Requests completed during one interval of a load test.
+#[derive(Clone, Debug)]
+pub struct IntervalStats {
+    pub start: Duration,
+    pub requests: usize,
+    pub errors: usize,
+    pub keys_per_sec: f64,
+    pub latency: Option<Summary>,
+}
+
+/** Every request made during a load test, in completion order.  The wall
+ * time runs until the last request completed, so it is a little longer than
+ * the duration the test was configured to run for.
+ */
+#[derive(Clone, Debug)]
+pub struct LoadResult {
+    pub wall: Duration,
+    pub duration: Duration,
+    pub interval: Duration,
+    pub completions: Vec<Completion>,
+}
+
+impl LoadResult {
+    // Latencies of the successful requests.
+    pub fn latencies(&self) -> Vec<Duration> {
+        self.completions.iter().filter(|c| c.error.is_none()).map(|c| c.latency).collect()
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.completions.iter().filter(|c| c.error.is_some()).count()
+    }
+
+    pub fn error_rate(&self) -> f64 {
+        if self.completions.is_empty() {0.0} else {self.error_count() as f64 / self.completions.len() as f64}
+    }
+
+    // Failed requests by error category.
+    pub fn errors(&self) -> BTreeMap<String, u64> {
+        let mut errors = BTreeMap::new();
+        for error in self.completions.iter().filter_map(|c| c.error.as_ref()) {
+            *errors.entry(error.clone()).or_default() += 1;
+        }
+        errors
+    }
+
+    // Keys successfully generated per second over the whole test.
+    pub fn keys_per_sec(&self) -> f64 {
+        (self.completions.len() - self.error_count()) as f64 / self.wall.as_secs_f64()
+    }
+
+    // The configured duration split into whole intervals by completion time.
+    // The remainder of the duration, and requests still running when it ran
+    // out, fall into the last interval, which is lengthened to cover the
+    // remainder so its rate isn't inflated by dividing by a sliver of time.
+    pub fn timeline(&self) -> Vec<IntervalStats> {
+        let step = self.interval.max(self.duration / MAX_INTERVALS as u32);
+        let interval = step.as_secs_f64();
+        let count = ((self.duration.as_secs_f64() / interval) as usize).clamp(1, MAX_INTERVALS);
+        let mut buckets = vec![Vec::new(); count];
+        for c in &self.completions {
+            let i = ((c.at.as_secs_f64() / interval) as usize).min(count - 1);
+            buckets[i].push(c);
+        }
+
+        buckets.into_iter().enumerate().map(|(i, completions)| {
+            // i is below MAX_INTERVALS, so it fits in a u32.
+            let start = step.saturating_mul(i as u32);
+            let length = if i + 1 == count {self.duration.saturating_sub(start)} else {step}.as_secs_f64();
+            let latencies: Vec<_> = completions.iter().filter(|c| c.error.is_none()).map(|c| c.latency).collect();
+            IntervalStats {
+                start,
+                requests: completions.len(),
+                errors: completions.len() - latencies.len(),
+                keys_per_sec: if length > 0.0 {latencies.len() as f64 / length} else {0.0},
+                latency: Summary::from_samples(&latencies),
+            }
+        }).collect()
+    }
+}
+
+// Run the load test, blocking until it is over and every request started has
+// completed.
+pub fn run(config: &LoadConfig) -> LoadResult {
+    let agent = ureq::AgentBuilder::new()
+        .timeout(config.timeout)
+        .max_idle_connections_per_host(config.max_in_flight)
+        .build();
+    let start = Instant::now();
+    // A duration too long to add to the clock runs until the process is stopped.
+    let deadline = start.checked_add(config.duration);
+    let running = || deadline.is_none_or(|d| Instant::now() < d);
+
+    let mut completions: Vec<Completion> = match config.mode {
+        LoadMode::Concurrency(clients) => thread::scope(|scope| {
+            let workers: Vec<_> = (0..clients.max(1)).map(|_| scope.spawn(|| {
+                let mut completions = Vec::new();
+                while running() {
+                    let sent = Instant::now();
+                    let error = send(&agent, config).err();
+                    completions.push(Completion {at: start.elapsed(), latency: sent.elapsed(), error});
+                }
+                completions
+            })).collect();
+            workers.into_iter().flat_map(|w| w.join().expect("load test client panicked")).collect()
+        }),
+        LoadMode::Rate(rate) => {
+            let (sender, receiver) = mpsc::channel::<Instant>();
+            let receiver = Mutex::new(receiver);
+            thread::scope(|scope| {
+                let workers: Vec<_> = (0..config.max_in_flight.max(1)).map(|_| scope.spawn(|| {
+                    let mut completions = Vec::new();
+                    loop {
+                        // Release the lock before sending, so only the wait for
+                        // the next scheduled start is serialized.
+                        let next = receiver.lock().expect("load test lock poisoned").recv();
+                        let Ok(due) = next else {
+                            break;
+                        };
+                        let error = send(&agent, config).err();
+                        completions.push(Completion {at: start.elapsed(), latency: due.elapsed(), error});
+                    }
+                    completions
+                })).collect();
+
+                for i in 0u64.. {
+                    let due = Duration::try_from_secs_f64(i as f64 / rate).ok().and_then(|d| start.checked_add(d));
+                    let Some(due) = due.filter(|due| deadline.is_none_or(|d| *due < d)) else {
+                        break;
+                    };
+                    thread::sleep(due.saturating_duration_since(Instant::now()));
+                    sender.send(due).expect("load test clients exited early");
+                }
+                drop(sender);
+                workers.into_iter().flat_map(|w| w.join().expect("load test client panicked")).collect()
+            })
+        }
+    };
+
+    completions.sort_by_key(|c| c.at);
+    LoadResult {wall: start.elapsed(), duration: config.duration, interval: config.interval, completions}
+}
+
+// Send one request, returning the error category if it failed.  A response
+// only counts as a success if it is a Security Kernel style success envelope.
+fn send(agent: &ureq::Agent, config: &LoadConfig) -> Result<(), String> {
+    let response = agent.post(&config.url)
+        .set("Content-Type", "application/json")
+        .send_string(&config.body)
+        .map_err(|e| match e {
+            ureq::Error::Status(code, _) => format!("HTTP {}", code),
+            ureq::Error::Transport(t) => t.kind().to_string(),
+        })?;
+    let body = response.into_string().map_err(|e| format!("unable to read response: {}", e.kind()))?;
+    match serde_json::from_str::<serde_json::Value>(&body) {
+        Ok(json) if json["status"] == "success" => Ok(()),
+        _ => Err("invalid response".to_string()),
+    }
+}
+
+// One row per interval of each load test, for plotting throughput over time.
+pub fn write_timeline_csv(path: &Path, results: &[(KeySpec, LoadResult)]) -> io::Result<()> {
+    let mut csv = String::from("key_spec,interval_start_s,requests,errors,keys_per_sec,p50_ns,p99_ns\n");
+    for (spec, result) in results {
+        for i in result.timeline() {
+            let (p50, p99) = i.latency.map(|s| (s.median.as_nanos(), s.p99.as_nanos())).unwrap_or_default();
+            csv += &format!("{},{},{},{},{:.3},{},{}\n", spec, i.start.as_secs_f64(), i.requests, i.errors,
+                            i.keys_per_sec, p50, p99);
+        }
+    }
+    fs::write(path, csv)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(duration: Duration, interval: Duration, at: &[Duration]) -> LoadResult {
+        let completions = at.iter().map(|&at| Completion {at, latency: Duration::from_millis(5), error: None}).collect();
+        let wall = at.iter().copied().max().unwrap_or_default().max(duration);
+        LoadResult {wall, duration, interval, completions}
+    }
+
+    #[test]
+    fn timeline_folds_the_remainder_into_the_last_interval() {
+        let at = [Duration::from_millis(100), Duration::from_millis(1500), Duration::from_millis(2400)];
+        let timeline = result(Duration::from_millis(2500), Duration::from_secs(1), &at).timeline();
+        assert_eq!(timeline.iter().map(|i| i.requests).collect::<Vec<_>>(), [1, 2]);
+        assert_eq!(timeline[1].start, Duration::from_secs(1));
+        assert!((timeline[1].keys_per_sec - 2.0 / 1.5).abs() < 1e-9, "{}", timeline[1].keys_per_sec);
+    }
+
+    // Requests still running at the deadline complete after it, and mustn't
+    // make a trailing interval whose rate is divided by a few milliseconds.
+    #[test]
+    fn late_completions_dont_inflate_the_rate() {
+        let mut at: Vec<_> = (0..74).map(|i| Duration::from_millis(i * 13)).collect();
+        at.extend([Duration::from_millis(1002), Duration::from_millis(1004)]);
+        let timeline = result(Duration::from_secs(1), Duration::from_secs(1), &at).timeline();
+        assert_eq!(timeline.len(), 1);
+        assert_eq!(timeline[0].requests, 76);
+        assert!((timeline[0].keys_per_sec - 76.0).abs() < 1e-9, "{}", timeline[0].keys_per_sec);
+    }
+
+    #[test]
+    fn timeline_caps_the_number_of_intervals() {
+        let at = [Duration::ZERO, Duration::from_secs(50), Duration::from_secs(100)];
+        let timeline = result(Duration::from_secs(100), Duration::from_nanos(1), &at).timeline();
+        assert!(timeline.len() <= MAX_INTERVALS && timeline.len() >= MAX_INTERVALS - 1, "{}", timeline.len());
+        assert_eq!(timeline.iter().map(|i| i.requests).sum::<usize>(), at.len());
+        assert_eq!(timeline[1].start, Duration::from_millis(10));
+    }
+
+    #[test]
+    fn timeline_of_a_very_long_test() {
+        let duration = Duration::from_secs(u64::MAX / 2);
+        let timeline = result(duration, Duration::from_secs(1), &[duration]).timeline();
+        assert!(timeline.len() <= MAX_INTERVALS);
+        assert_eq!(timeline.last().map(|i| i.requests), Some(1));
+    }
+}
@@ -1,105 +1,560 @@
-use ssh_key::{Algorithm, HashAlg, EcdsaCurve, PrivateKey};
-use rand_core::CryptoRngCore; // rand is implicitly exposed
-use std::{ops::Deref, time::Instant};
+use std::{io, path::{Path, PathBuf}, time::{Duration, Instant}};
+use clap::Parser;
 
-/** This program records the time it takes to generate SSH keys using the different 
- * algorithms supported by the ssh-key crate.  Details about the options set for 
+use sshkeytest::{alg, authorized_keys, baseline, budget, chart, parallel};
+use sshkeytest::budget::{Budget, BudgetRun};
+use sshkeytest::cert::{CertIssuer, CertOptions};
+use sshkeytest::fingerprint::{self, FingerprintHash};
+use sshkeytest::keygen::{GenOptions, GeneratedKey};
+use sshkeytest::known_hosts::{self, HostEntry};
+use sshkeytest::load::{self, LoadResult};
+use sshkeytest::pool::KeyPool;
+use sshkeytest::server::KeyServer;
+use sshkeytest::alg::{KeyAlg, KeySpec};
+use sshkeytest::baseline::Verdict;
+use sshkeytest::report::{AlgResult, BudgetResult, EncryptionResult, Report, ScalingPoint};
+use sshkeytest::rng::{RngConfig, RngKind};
+use sshkeytest::stats::{Histogram, Summary};
+
+mod cli;
+
+use cli::{BenchArgs, Cli, Command, KnownHostsArgs, LoadArgs, PoolArgs, ServeArgs};
+
+/** This program records the time it takes to generate SSH keys using the different
+ * algorithms supported by the ssh-key crate.  Details about the options set for
  * each algorithm can be discovered by drilling down into the source code of
- * PrivateKey::random() in the gen_ssh_keys() function.
- * 
- * The optimized ED25519 results are the clear winner on this machine, taking 29 
- * microseconds on average to generate a key.  Generating keys is about 30 times 
- * slower for optimized ECDSA and about 250,000 times slower for optimized RSA.  
- * 
+ * PrivateKey::random() and RsaKeypair::random() in KeySpec::generate().
+ *
+ * The key generation code lives in the sshkeytest library (src/lib.rs) so that
+ * other services can use it directly; this binary only parses the command line
+ * and prints and saves the results.
+ *
+ * The optimized ED25519 results are the clear winner on this machine, taking 29
+ * microseconds on average to generate a key.  Generating keys is about 30 times
+ * slower for optimized ECDSA and about 250,000 times slower for optimized RSA.
+ *
  * Running the Program
  * -------------------
- * Change the RSA_ITERATIONS and ITERATIONS constants at the top of main() to
- * specify the number of keys to be generated.  By default, the first key's 
- * information is printed to stdout.  Adjust as needed.
- * 
+ * Use the bench subcommand to generate keys, for example:
+ *
+ *      cargo run --release -- bench --alg ecdsa,ed25519 --iterations 1000
+ *      cargo run --release -- bench --alg rsa --rsa-iterations 5 --rng thread
+ *      cargo run --release -- bench --alg ecdsa --curves p256,p384
+ *      cargo run --release -- bench --alg rsa --rsa-bits 2048,3072,4096,8192
+ *      cargo run --release -- bench --alg rsa --rsa-iterations 64 --threads 8 --scaling
+ *      cargo run --release -- bench --time-budget 30
+ *      cargo run --release -- bench --alg rsa,ed25519 --target-error 2 --time-budget 300
+ *      cargo run --release -- bench --alg ed25519 --rng chacha20 --seed 42
+ *      cargo run --release -- bench --alg ed25519 -n 1 --fingerprint md5 --randomart
+ *      cargo run --release -- bench --alg ed25519,rsa -n 1 --export keys --comment alice@example.com
+ *      cargo run --release -- bench --alg ed25519 -n 20 --authorized-keys authorized_keys --from 10.0.0.0/8 --no-port-forwarding
+ *      cargo run --release -- bench --alg ecdsa -n 10 --comment '{user}@tapis-{alg}-{index}'
+ *      cargo run --release -- bench --alg ed25519 --passphrase secret --kdf-rounds 64
+ *      cargo run --release -- bench --alg rsa --rsa-bits 2048 --rsa-iterations 20 --verify
+ *      cargo run --release -- bench --alg ecdsa,ed25519 --serialize --csv-ops encodings.csv
+ *      cargo run --release -- bench --alg rsa,ecdsa,ed25519 --histogram --charts charts
+ *      cargo run --release -- bench --alg ed25519,ecdsa --sign --sign-sizes 32,4096 --namespace file
+ *      cargo run --release -- bench --alg rsa --rsa-bits 2048,4096 --sign --rsa-hashes sha256,sha512
+ *      cargo run --release -- bench --alg ecdsa --cert --ca rsa-3072 --principal alice,bob
+ *      cargo run --release -- bench --save-baseline main
+ *      cargo run --release -- bench --baseline main --threshold 10
+ *
+ * RSA keys above 4096 bits can be generated and sign, but the rsa crate won't
+ * verify with them: --verify skips their signature check, --sign only times
+ * signing and --cert refuses them as a CA.
+ *
+ * Use the known-hosts subcommand to bootstrap a cluster's host keys, for example:
+ *
+ *      cargo run --release -- known-hosts --host node1.cluster,node2.cluster --key ed25519,ecdsa-p256 --hash
+ *      cargo run --release -- known-hosts --host node1.cluster --cert-authority '*.cluster' --key-dir hostkeys
+ *
+ * Use the pool subcommand to see how well a pool of pre-generated keys keeps up
+ * with demand, for example:
+ *
+ *      cargo run --release -- pool --key rsa-3072 --low 2 --high 8 -n 20 --interval-ms 500
+ *
+ * Use the serve subcommand to stand in for the Tapis Security Kernel's key
+ * generation endpoint in integration tests, for example:
+ *
+ *      cargo run --release -- serve --listen 127.0.0.1:8080 --pool rsa-3072
+ *      curl -d '{"algorithm": "rsa", "size": 3072, "comment": "alice@tapis"}' http://127.0.0.1:8080/keys
+ *
+ * and the load subcommand to measure how such a service holds up, for example:
+ *
+ *      cargo run --release -- load --key ed25519,rsa-3072 --concurrency 8 --duration 30
+ *      cargo run --release -- load --key rsa-2048 --rate 20 --csv-timeline load.csv
+ *
+ * By default, the first key's information is printed to stdout; pass --quiet
+ * to suppress it.  Run with --help for the full list of options and the list
+ * subcommand for the valid algorithm and random number generator names.
+ *
  */
 fn main() {
-    //******************************************************
-    // ****** CONFIGURE THESE PARAMETERS FOR EACH RUN ****** 
-    const RSA_ITERATIONS: u32 = 1;  // rsa generation is very slow, especially in dev builds
-    const ITERATIONS: u32 = 1000;   // ed25519 and ecdsa are pretty fast
-    #[allow(non_snake_case)]
-    let TEST_ALGS = ["RSA", "ECDSA", "ED25519"];  // valid options: ["RSA", "ECDSA", "ED25519"]
-    // ****** END OF PARAMETER CONFIGURATION *************** 
-    //******************************************************
-
-    // Operating system's random number generator.
-    let mut rng = rand::rngs::OsRng;
-    // Secure thread-safe PRNG. See rand_chacha and ThreadRng for more info. 
-    // let mut rng = rand::thread_rng(); // chacha prng
-
-    // ------- Ecdsa 
-    if TEST_ALGS.contains(&"ECDSA") {
-        let curve = EcdsaCurve::NistP521;
-        let start = Instant::now();
-        gen_ssh_keys(ITERATIONS, &mut rng, Algorithm::Ecdsa {curve});
-        let duration = start.elapsed();
-        println!("Time to generate {} {} keys: {:?} ({:?} per key)", ITERATIONS, 
-                (Algorithm::Ecdsa {curve}.to_string()), duration, duration/ITERATIONS);
-    }
-
-    // ------- Ed25519 
-    if TEST_ALGS.contains(&"ED25519") {
-        let start = Instant::now();
-        gen_ssh_keys(ITERATIONS, &mut rng, Algorithm::Ed25519);
-        let duration = start.elapsed();
-        println!("Time to generate {} {} keys: {:?} ({:?} per key)", ITERATIONS, 
-                (Algorithm::Ed25519.to_string()), duration, duration/ITERATIONS);
-    }
-    
-    // ------- RSA 
-    if TEST_ALGS.contains(&"RSA") {
-        let hash = Some(HashAlg::Sha256);
-        let start = Instant::now();
-        gen_ssh_keys(RSA_ITERATIONS, &mut rng, Algorithm::Rsa {hash});
-        let duration = start.elapsed();
-        println!("Time to generate {} {} keys: {:?} ({:?} per key)", RSA_ITERATIONS, 
-                (Algorithm::Rsa {hash}.to_string()), duration, duration/RSA_ITERATIONS);
-    }
-}
-
-#[allow(unused_variables)]
-fn gen_ssh_keys(iterations: u32, rng: &mut impl CryptoRngCore, algorithm: Algorithm) {
-    // Announce this test.
-    println!("\n>>>>>>>>>> Beginning test of {} iterations of {} keys.", iterations, algorithm.to_string());
-
-    // Create keys in a loop.
-    let mut key_cnt = 0;
-    while key_cnt < iterations {
-        // Increment key counter.
-        key_cnt += 1;
-
-        let alg = algorithm.clone();
-        let key = match PrivateKey::random(rng, alg) {
-            Ok(k) => k,
-            Err(e) => {
-                panic!("Key generation failed: {}", e);
+    let cli = Cli::parse();
+    match cli.command {
+        Command::Bench(args) => {
+            args.validate();
+            run_bench(&args)
+        }
+        Command::KnownHosts(args) => {
+            args.validate();
+            run_known_hosts(&args)
+        }
+        Command::Pool(args) => {
+            args.validate();
+            run_pool(&args)
+        }
+        Command::Serve(args) => {
+            args.validate();
+            run_serve(&args)
+        }
+        Command::Load(args) => {
+            args.validate();
+            run_load(&args)
+        }
+        Command::List => list_options(),
+    }
+}
+
+fn run_bench(args: &BenchArgs) {
+    // Read the baseline first, so a missing one doesn't cost a whole run.
+    let baseline = args.baseline.as_ref().map(|name| read_baseline(args, name));
+    let rng_config = args.rng_config();
+    let mut options = args.gen_options();
+    options.cert = args.cert_options().map(|cert_options| new_issuer(args, cert_options));
+    if let Some(comment) = &options.comment {
+        println!(">>>>>>>>>> Commenting keys with '{}'", comment);
+    }
+    if let Some(export) = &options.export {
+        println!(">>>>>>>>>> Writing up to {} key(s) per algorithm to {}", export.count, export.dir.display());
+    }
+    if let Some(sign) = &options.sign {
+        let sizes: Vec<_> = sign.message_sizes().map(|s| s.to_string()).collect();
+        println!(">>>>>>>>>> Signing {} byte messages in namespace '{}' with {}", sizes.join(", "),
+                 sign.namespace, sign.hash_alg);
+    }
+    let unverifiable: Vec<_> = args.key_specs().into_iter().filter(|s| !s.can_verify()).map(|s| s.to_string()).collect();
+    if options.verify && !unverifiable.is_empty() {
+        println!(">>>>>>>>>> Skipping the signature check of --verify for {}: RSA keys above {} bits can't verify",
+                 unverifiable.join(", "), alg::RSA_VERIFY_MAX_BITS);
+    }
+    if options.sign.is_some() && !unverifiable.is_empty() {
+        println!(">>>>>>>>>> Timing only signing for {}: RSA keys above {} bits can't verify",
+                 unverifiable.join(", "), alg::RSA_VERIFY_MAX_BITS);
+    }
+    let mut report = Report::new(&rng_config);
+    let threads = args.threads as usize;
+    let mut public_keys = Vec::new();
+
+    for spec in args.key_specs() {
+        let (run, budget_result) = match args.budget() {
+            Some(budget) => {
+                let run = run_budgeted(&budget, threads, rng_config, &spec, &options);
+                let result = BudgetResult::new(&budget, &run);
+                (run.run, Some(result))
+            }
+            None => {
+                let iterations = args.iterations_for(spec.family);
+
+                // Announce this test.
+                print!("\n>>>>>>>>>> Beginning test of {} iterations of {}", iterations, describe(&spec));
+                if threads > 1 {
+                    print!(" on {} threads", threads);
+                }
+                println!(".");
+
+                (parallel::gen_parallel(iterations, threads, rng_config, &spec, &options), None)
             }
         };
+        let iterations = run.keys() as u32;
+        let samples = run.samples();
+        public_keys.extend(run.public_keys());
+        println!("Time to generate {} {}: {:?} ({:?} per key)", iterations,
+                describe(&spec), run.wall, run.wall / iterations.max(1));
+        if threads > 1 {
+            println!("Aggregate throughput: {:.1} keys/s", run.keys_per_sec());
+            run.print_threads();
+        }
+
+        let summary = Summary::from_samples(&samples);
+        match &summary {
+            Some(summary) => {
+                println!("Per key generation latency:");
+                summary.print();
+                if args.histogram {
+                    if let Some(histogram) = Histogram::from_samples(&samples) {
+                        print_histogram(&histogram);
+                    }
+                }
+            }
+            None => println!("No keys could be generated."),
+        }
+        let mut result = AlgResult::new(&spec, &run, &samples, summary.as_ref());
+        result.budget = budget_result;
+        if let Some(encrypt) = &options.encrypt {
+            result.encryption = EncryptionResult::new(encrypt, &run);
+            print_encryption(encrypt.kdf_rounds, &run);
+        }
+        print_operations(&run);
+        if !result.failures.is_empty() {
+            let f = &result.failures;
+            println!("Failures: {} errors, {} retries, {} keys not generated, {} keys' step samples dropped",
+                     f.total_errors(), f.retries, f.failed_keys, f.dropped_samples);
+        }
+        if args.scaling && summary.is_some() {
+            let max_threads = if threads > 1 {threads} else {parallel::available_threads()};
+            let runs = parallel::scaling_curve(iterations, max_threads, rng_config, &spec, &options);
+            result.scaling = ScalingPoint::from_runs(&runs);
+            print_scaling(iterations, &result.scaling);
+        }
+        report.results.push(result);
+    }
+
+    print_failures(&report);
+    if let Some(path) = &args.authorized_keys {
+        let result = authorized_keys::write(path, &public_keys, &args.key_options(), args.force);
+        check_written(path, result);
+        println!("Verified {} authorized_keys entries.", public_keys.len());
+    }
+    write_report(&report, args);
+    check_baselines(&report, baseline.as_ref(), args);
+}
+
+// Generate keys within the budget, announcing the test and how it ended.
+fn run_budgeted(budget: &Budget, threads: usize, rng_config: RngConfig, spec: &KeySpec,
+                options: &GenOptions) -> BudgetRun {
+    print!("\n>>>>>>>>>> Beginning test of {} for up to {:?}", describe(spec), budget.time);
+    if let Some(target) = budget.target_error {
+        print!(" or until within ±{}% of the mean", target * 100.0);
+    }
+    if threads > 1 {
+        print!(" on {} threads", threads);
+    }
+    println!(".");
+
+    let run = budget::gen_budgeted(budget, threads, rng_config, spec, options);
+    print!("Discarded {} warm-up key(s); stopped with {}", run.warmup_keys, run.stop);
+    match run.relative_error {
+        Some(e) => println!(" (95% confidence interval ±{:.2}% of the mean).", e * 100.0),
+        None => println!("."),
+    }
+    run
+}
 
-        // Print first key.
-        if key_cnt == 1 {
-            println!("------- Private key:");
-            if let Ok(k) = key.to_openssh(ssh_key::LineEnding::LF) {
-                println!("{}", k.to_string());
+// Generate the certificate authority used for every certificate in the run.
+// The CA gets the last RNG stream so a seeded run stays reproducible without
+// sharing a stream with any worker.
+fn new_issuer(args: &BenchArgs, cert_options: CertOptions) -> CertIssuer {
+    let mut rng = args.rng_config().new_rng(u64::MAX);
+    match CertIssuer::generate(&args.ca, cert_options, &mut rng) {
+        Ok(issuer) => {
+            let fp = fingerprint::fingerprint(issuer.ca.public_key(), args.fingerprint)
+                .unwrap_or_else(|e| exit_with("Unable to fingerprint certificate authority", e));
+            println!(">>>>>>>>>> Issuing {:?} certificates with {} CA {}", issuer.options.cert_type, args.ca, fp);
+            issuer
+        }
+        Err(e) => {
+            eprintln!("Unable to create certificate authority: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+// Print the latency of each other per-key operation, such as encoding, signing
+// or certificate issuance.
+fn print_operations(run: &parallel::ParallelRun) {
+    for (op, samples) in run.op_samples() {
+        if let Some(summary) = Summary::from_samples(&samples) {
+            println!("Per key {} latency:", op);
+            summary.print();
+        }
+    }
+}
+
+// Generate host keys, and host certificates if there is a CA, for every host
+// and write them to a known_hosts file that is then read back and verified.
+fn run_known_hosts(args: &KnownHostsArgs) {
+    let mut rng = rand::rngs::OsRng;
+
+    let mut entries = Vec::new();
+    let issuer = args.cert_authority.as_ref().map(|pattern| {
+        let issuer = CertIssuer::generate(&args.ca, args.cert_options(), &mut rng)
+            .unwrap_or_else(|e| exit_with("Unable to create certificate authority", e));
+        let fp = fingerprint::fingerprint(issuer.ca.public_key(), FingerprintHash::Sha256)
+            .unwrap_or_else(|e| exit_with("Unable to fingerprint certificate authority", e));
+        println!("Certificate authority for {}: {}", pattern, fp);
+        entries.push(HostEntry::cert_authority(pattern, issuer.ca.public_key().clone()));
+        issuer
+    });
+
+    for host in &args.hosts {
+        for spec in &args.keys {
+            let mut key = GeneratedKey::generate(spec, &mut rng)
+                .unwrap_or_else(|e| exit_with(&format!("Unable to generate {} host key for {}", spec, host), e))
+                .private_key;
+            key.set_comment(host.as_str());
+            let fp = fingerprint::fingerprint(key.public_key(), FingerprintHash::Sha256)
+                .unwrap_or_else(|e| exit_with(&format!("Unable to fingerprint {} host key for {}", spec, host), e));
+            println!("{} {}: {}", host, spec, fp);
+
+            if let Some(dir) = &args.key_dir {
+                let dir = dir.join(known_hosts::host_name(host));
+                let written = known_hosts::write_host_key(&dir, host, spec, &key, issuer.as_ref(), args.force, &mut rng)
+                    .unwrap_or_else(|e| exit_with(&format!("Unable to write {} host key for {}", spec, host), e));
+                for path in written {
+                    println!("Wrote {}", path.display());
+                }
             }
+            entries.push(if args.hash {
+                HostEntry::hashed_host(host, key.public_key().clone(), &mut rng)
+            } else {
+                HostEntry::host(host, key.public_key().clone())
+            });
+        }
+    }
 
-            if let Ok(b) = key.to_bytes() {
-                let v = b.deref();
-                println!("Key length in bytes = {}", v.len());
+    let result = known_hosts::write(&args.output, &entries, &args.hosts, args.force);
+    check_written(&args.output, result);
+    println!("Verified {} known_hosts entries for {} hosts.", entries.len(), args.hosts.len());
+}
+
+// Fill a pool, take keys from it round robin and report how long takes waited
+// and how the pool kept up.
+fn run_pool(args: &PoolArgs) {
+    let start = Instant::now();
+    let pool = KeyPool::new(args.pool_config())
+        .unwrap_or_else(|e| exit_with("Unable to create key pool", e));
+    println!(">>>>>>>>>> Filling pool with {} key(s) of each of {}", args.high,
+             args.keys.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(", "));
+    if pool.wait_full(Duration::from_secs(args.fill_timeout)) {
+        println!("Pool filled in {:?}", start.elapsed());
+    } else {
+        println!("Pool not full after {} seconds; taking keys anyway.", args.fill_timeout);
+    }
+
+    println!("\n>>>>>>>>>> Taking {} key(s) of each spec, {} ms apart.", args.takes, args.interval_ms);
+    let mut take_samples = vec![Vec::new(); args.keys.len()];
+    for _ in 0..args.takes {
+        for (spec, samples) in args.keys.iter().zip(&mut take_samples) {
+            let start = Instant::now();
+            match pool.take(spec) {
+                Ok(_) => samples.push(start.elapsed()),
+                Err(e) => eprintln!("Unable to take {} key: {}", spec, e),
             }
+            std::thread::sleep(Duration::from_millis(args.interval_ms));
+        }
+    }
+
+    for (stats, samples) in pool.stats().iter().zip(&take_samples) {
+        println!("\n{} pool: {} hits, {} misses ({:.1}% hit rate), {} refills, {} keys generated, {} available",
+                 stats.spec, stats.hits, stats.misses, stats.hit_rate() * 100.0, stats.refills,
+                 stats.generated, stats.available);
+        if let Some(summary) = Summary::from_samples(samples) {
+            println!("Per key take latency:");
+            summary.print();
+        }
+        if let Some(summary) = &stats.refill {
+            println!("Per key refill latency:");
+            summary.print();
+        }
+        if !stats.failures.is_empty() {
+            println!("Refill failures:");
+            stats.failures.print();
+        }
+    }
+}
+
+fn run_serve(args: &ServeArgs) {
+    let server = KeyServer::bind(args.server_config())
+        .unwrap_or_else(|e| exit_with(&format!("Unable to listen on {}", args.listen), e));
+    let addr = server.local_addr().map(|a| a.to_string()).unwrap_or_else(|| args.listen.clone());
+    println!(">>>>>>>>>> Serving POST http://{}/keys on {} threads", addr, args.threads);
+    if !args.pool_keys.is_empty() {
+        println!("Pooling {} to {} key(s) of each of {}", args.low, args.high,
+                 args.pool_keys.iter().map(|s| s.to_string()).collect::<Vec<_>>().join(", "));
+    }
+    server.run();
+}
+
+fn run_load(args: &LoadArgs) {
+    let mut results = Vec::new();
+    for spec in &args.keys {
+        let config = args.load_config(spec);
+        println!("\n>>>>>>>>>> Requesting {} from {} ({}) for {} seconds.", describe(spec), config.url,
+                 config.mode, args.duration);
+        let result = load::run(&config);
+        print_load(&result);
+        results.push((spec.clone(), result));
+    }
+    if let Some(path) = &args.csv_timeline {
+        check_written(path, load::write_timeline_csv(path, &results));
+    }
+}
+
+fn print_load(result: &LoadResult) {
+    println!("Completed {} requests in {:?}: {:.1} keys/s, {} errors ({:.1}%)", result.completions.len(),
+             result.wall, result.keys_per_sec(), result.error_count(), result.error_rate() * 100.0);
+    for (category, count) in result.errors() {
+        println!("  {:>6} x {}", count, category);
+    }
+
+    let latencies = result.latencies();
+    let Some(summary) = Summary::from_samples(&latencies) else {
+        return;
+    };
+    println!("Per request latency:");
+    summary.print();
+    if let Some(histogram) = Histogram::from_samples(&latencies) {
+        print_histogram(&histogram);
+    }
+
+    println!("Over time:");
+    println!("  {:>8} {:>8} {:>6} {:>10} {:>12} {:>12}", "time", "requests", "errors", "keys/s", "p50", "p99");
+    for i in result.timeline() {
+        let (p50, p99) = match &i.latency {
+            Some(s) => (format!("{:.2?}", s.median), format!("{:.2?}", s.p99)),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        println!("  {:>7.1}s {:>8} {:>6} {:>10.1} {:>12} {:>12}", i.start.as_secs_f64(), i.requests,
+                 i.errors, i.keys_per_sec, p50, p99);
+    }
+}
+
+fn print_histogram(histogram: &Histogram) {
+    println!("Latency histogram:");
+    print!("{}", chart::ascii_histogram(histogram, chart::ASCII_WIDTH));
+}
+
+fn exit_with(context: &str, error: impl std::fmt::Display) -> ! {
+    eprintln!("{}: {}", context, error);
+    std::process::exit(1);
+}
+
+// Summarize every error category across the run, if anything went wrong.
+fn print_failures(report: &Report) {
+    let failed: Vec<_> = report.results.iter().filter(|r| !r.failures.is_empty()).collect();
+    if failed.is_empty() {
+        return;
+    }
+    println!("\nError summary:");
+    for r in failed {
+        println!("{} bit {}: {} errors", r.key_size, r.algorithm, r.failures.total_errors());
+        r.failures.print();
+    }
+}
+
+// Compare with and then save baselines as requested, exiting with an error if
+// the comparison found a regression.
+fn check_baselines(report: &Report, saved: Option<&Report>, args: &BenchArgs) {
+    let mut regressions = 0;
+    if let (Some(name), Some(saved)) = (&args.baseline, saved) {
+        if saved.build_profile != report.build_profile {
+            println!("\nWarning: baseline '{}' is a {} build and this is a {} build.", name,
+                     saved.build_profile, report.build_profile);
+        }
+        let comparisons = baseline::compare(saved, report, args.threshold / 100.0, args.alpha);
+        baseline::print_comparisons(name, &comparisons);
+        regressions = comparisons.iter().filter(|c| c.verdict == Verdict::Regression).count();
+    }
+
+    if let Some(name) = &args.save_baseline {
+        let path = baseline_path(&args.baseline_dir, name);
+        let result = std::fs::create_dir_all(&args.baseline_dir)
+            .and_then(|_| report.write_json(&path));
+        check_written(&path, result);
+    }
+
+    if regressions > 0 {
+        eprintln!("{} regression(s) detected.", regressions);
+        std::process::exit(1);
+    }
+}
+
+// Read the named baseline, exiting if it can't be read.
+fn read_baseline(args: &BenchArgs, name: &str) -> Report {
+    let path = baseline_path(&args.baseline_dir, name);
+    Report::read_json(&path).unwrap_or_else(|e| exit_with(&format!("Unable to read baseline {}", path.display()), e))
+}
+
+// Baseline names were checked by BenchArgs::validate().
+fn baseline_path(dir: &Path, name: &str) -> PathBuf {
+    baseline::baseline_path(dir, name).expect("invalid baseline name")
+}
 
-            if let Ok(pubk) = key.public_key().to_openssh() {
-                println!("\n------- Public key: \n{}", pubk);
+fn print_encryption(kdf_rounds: u32, run: &parallel::ParallelRun) {
+    for (step, samples) in [("encryption", run.encrypt_samples()), ("decryption", run.decrypt_samples())] {
+        if let Some(summary) = Summary::from_samples(&samples) {
+            println!("Per key {} latency (bcrypt-pbkdf, {} rounds):", step, kdf_rounds);
+            summary.print();
+        }
+    }
+}
+
+fn print_scaling(iterations: u32, points: &[ScalingPoint]) {
+    println!("Scaling ({} keys per run):", iterations);
+    println!("  {:>7} {:>12} {:>8} {:>10}", "threads", "keys/s", "speedup", "efficiency");
+    for p in points {
+        println!("  {:>7} {:>12.1} {:>7.2}x {:>9.0}%", p.threads, p.keys_per_sec, p.speedup,
+                 p.efficiency * 100.0);
+    }
+}
+
+// Write the report files requested on the command line.
+fn write_report(report: &Report, args: &BenchArgs) {
+    if let Some(path) = &args.json {
+        check_written(path, report.write_json(path));
+    }
+    if let Some(path) = &args.csv {
+        check_written(path, report.write_csv(path));
+    }
+    if let Some(path) = &args.csv_samples {
+        check_written(path, report.write_samples_csv(path));
+    }
+    if let Some(path) = &args.csv_ops {
+        check_written(path, report.write_ops_csv(path));
+    }
+    if let Some(dir) = &args.charts {
+        match chart::write_charts(dir, report) {
+            Ok(paths) => paths.iter().for_each(|p| println!("Wrote {}", p.display())),
+            Err(e) => {
+                eprintln!("Unable to write charts to {}: {}", dir.display(), e);
+                std::process::exit(1);
             }
+        }
+    }
+}
 
-            let fp = key.fingerprint(HashAlg::Sha256); 
-            println!("\n------- fingerprint: \n{}", fp);
+fn check_written(path: &Path, result: io::Result<()>) {
+    match result {
+        Ok(()) => println!("Wrote {}", path.display()),
+        Err(e) => {
+            eprintln!("Unable to write {}: {}", path.display(), e);
+            std::process::exit(1);
         }
     }
 }
+
+fn list_options() {
+    println!("Algorithms:");
+    for alg in KeyAlg::ALL {
+        println!("  {}", alg.name());
+    }
+    println!("\nECDSA curves:");
+    for curve in alg::ALL_CURVES {
+        println!("  {:<10} {}", curve.as_str().trim_start_matches("nist"), KeySpec::ecdsa(curve).algorithm);
+    }
+    println!("\nFingerprint hashes:");
+    for hash in FingerprintHash::ALL {
+        println!("  {}", hash);
+    }
+    println!("\nRandom number generators:");
+    for kind in RngKind::ALL {
+        let seedable = if kind.is_seedable() {"(accepts --seed)"} else {""};
+        println!("  {:<10} {}", kind.name(), seedable);
+    }
+}
+
+// A description of the keys for progress messages, e.g. "3072 bit rsa-sha2-512 keys".
+fn describe(spec: &KeySpec) -> String {
+    format!("{} bit {} keys", spec.key_size, spec.algorithm)
+}
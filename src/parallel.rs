@@ -0,0 +1,167 @@
+use ssh_key::PublicKey;
+use std::{collections::BTreeMap, thread, time::{Duration, Instant}};
+
+use crate::alg::KeySpec;
+use crate::error::Failures;
+use crate::keygen::{gen_ssh_keys, GenOptions, KeyRun};
+use crate::rng::RngConfig;
+use crate::stats::Summary;
+
+/** The outcome of generating keys on one or more worker threads.  Each worker
+ * creates its own random number generator, seeded generators using the worker
+ * index as their stream, and times every key it generates.  The wall time
+ * covers all workers from the first spawn to the last join.
+ */
+#[derive(Clone, Debug)]
+pub struct ParallelRun {
+    pub threads: usize,
+    pub wall: Duration,
+    pub per_thread: Vec<KeyRun>,
+}
+
+impl ParallelRun {
+    pub fn keys(&self) -> usize {
+        self.per_thread.iter().map(|r| r.samples.len()).sum()
+    }
+
+    // Aggregate throughput across all workers.
+    pub fn keys_per_sec(&self) -> f64 {
+        self.keys() as f64 / self.wall.as_secs_f64()
+    }
+
+    // Every worker's generation samples combined, in worker order.
+    pub fn samples(&self) -> Vec<Duration> {
+        self.combined(|r| &r.samples)
+    }
+
+    // Every worker's encryption samples combined, in worker order.
+    pub fn encrypt_samples(&self) -> Vec<Duration> {
+        self.combined(|r| &r.encrypt_samples)
+    }
+
+    // Every worker's decryption samples combined, in worker order.
+    pub fn decrypt_samples(&self) -> Vec<Duration> {
+        self.combined(|r| &r.decrypt_samples)
+    }
+
+    // Every worker's samples for each other per-key operation, in worker order.
+    pub fn op_samples(&self) -> BTreeMap<String, Vec<Duration>> {
+        let mut ops: BTreeMap<String, Vec<Duration>> = BTreeMap::new();
+        for run in &self.per_thread {
+            for (op, samples) in &run.ops {
+                ops.entry(op.clone()).or_default().extend(samples);
+            }
+        }
+        ops
+    }
+
+    // Every worker's collected public keys, in worker order.
+    pub fn public_keys(&self) -> Vec<PublicKey> {
+        self.per_thread.iter().flat_map(|r| r.keys.iter().map(|k| k.public_key().clone())).collect()
+    }
+
+    fn combined(&self, samples: impl Fn(&KeyRun) -> &Vec<Duration>) -> Vec<Duration> {
+        self.per_thread.iter().flat_map(|r| samples(r).iter().copied()).collect()
+    }
+
+    // Every worker's failures combined.
+    pub fn failures(&self) -> Failures {
+        let mut failures = Failures::default();
+        for run in &self.per_thread {
+            failures.merge(&run.failures);
+        }
+        failures
+    }
+
+    // Print each worker's key count and latency, one line per thread.
+    pub fn print_threads(&self) {
+        for (i, run) in self.per_thread.iter().enumerate() {
+            match Summary::from_samples(&run.samples) {
+                Some(s) => println!("  thread {:>3}: {:>6} keys, mean {:?}, p99 {:?}", i, s.count, s.mean, s.p99),
+                None => println!("  thread {:>3}:      0 keys", i),
+            }
+        }
+    }
+}
+
+// Generate iterations keys spread as evenly as possible across the given
+// number of threads.  Only the first thread prints its first key; every thread
+// exports its keys whose index is within the export count, and a single
+// thread runs on the calling thread rather than a spawned one.
+pub fn gen_parallel(iterations: u32, threads: usize, rng_config: RngConfig, spec: &KeySpec,
+                    options: &GenOptions) -> ParallelRun {
+    let threads = threads.max(1);
+    let start = Instant::now();
+    let per_thread = if threads == 1 {
+        let mut rng = rng_config.new_rng(0);
+        vec![gen_ssh_keys(iterations, &mut rng, spec, options)]
+    } else {
+        let quiet = GenOptions {print_first: false, ..options.clone()};
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads).map(|i| {
+                let count = share(iterations, threads, i);
+                let first_index = (0..i).map(|j| share(iterations, threads, j)).sum();
+                let options = GenOptions {first_index, ..if i == 0 {options.clone()} else {quiet.clone()}};
+                scope.spawn(move || {
+                    let mut rng = rng_config.new_rng(i as u64);
+                    gen_ssh_keys(count, &mut rng, spec, &options)
+                })
+            }).collect();
+            workers.into_iter()
+                .map(|w| w.join().expect("Key generation thread panicked"))
+                .collect()
+        })
+    };
+
+    ParallelRun {threads, wall: start.elapsed(), per_thread}
+}
+
+// Generate the same number of keys with 1, 2, ... max_threads threads so that
+// throughput can be compared as threads are added.
+pub fn scaling_curve(iterations: u32, max_threads: usize, rng_config: RngConfig,
+                     spec: &KeySpec, options: &GenOptions) -> Vec<ParallelRun> {
+    let options = GenOptions {print_first: false, export: None, collect_keys: false, ..options.clone()};
+    (1..=max_threads.max(1))
+        .map(|threads| gen_parallel(iterations, threads, rng_config, spec, &options))
+        .collect()
+}
+
+// The number of threads this host can run in parallel.
+pub fn available_threads() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+// Worker i's share of the iterations; the remainder goes to the first workers.
+fn share(iterations: u32, threads: usize, i: usize) -> u32 {
+    let threads = threads as u32;
+    let i = i as u32;
+    iterations / threads + u32::from(i < iterations % threads)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::ExportOptions;
+    use crate::rng::RngKind;
+    use std::fs;
+
+    // Keys are numbered across threads, so the first export count of them are
+    // exported whichever thread generated them.
+    #[test]
+    fn exports_the_first_keys_across_threads() {
+        let dir = std::env::temp_dir().join(format!("sshkeytest-parallel-export-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let spec: KeySpec = "ed25519".parse().expect("spec");
+        let export = ExportOptions {dir: dir.clone(), count: 3, force: false};
+        let options = GenOptions {export: Some(export), ..Default::default()};
+        gen_parallel(6, 3, RngConfig {kind: RngKind::Os, seed: None}, &spec, &options);
+
+        let mut names: Vec<_> = fs::read_dir(&dir).expect("export dir")
+            .map(|e| e.expect("entry").file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["id_ed25519", "id_ed25519.pub", "id_ed25519_2", "id_ed25519_2.pub",
+                           "id_ed25519_3", "id_ed25519_3.pub"]);
+        fs::remove_dir_all(&dir).expect("cleaned up");
+    }
+}
@@ -0,0 +1,372 @@
+use ssh_key::PrivateKey;
+use std::collections::VecDeque;
+use std::io;
+use std::sync::{atomic::{AtomicU64, Ordering}, Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::alg::KeySpec;
+use crate::error::{Failures, KeyError};
+use crate::keygen::{gen_ssh_keys, GenOptions};
+use crate::rng::RngConfig;
+use crate::stats::Summary;
+
+// The most refill samples kept per queue; older samples are dropped so a
+// long-running pool's memory use stays bounded.
+const MAX_SAMPLES: usize = 10_000;
+
+/** How a KeyPool is stocked.  Each spec gets its own queue; when a queue
+ * drops below low_watermark keys the refill thread tops it back up to
+ * high_watermark, which must be at least low_watermark.  Keys are generated
+ * with gen_ssh_keys() using options, so comment templates and retries apply,
+ * with each spec's keys numbered in the order they are generated, whether
+ * by the refill thread or on a miss; print_first, export and encrypt are
+ * ignored since pooled keys are handed out unencrypted.
+ */
+#[derive(Clone, Debug)]
+pub struct PoolConfig {
+    pub specs: Vec<KeySpec>,
+    pub low_watermark: usize,
+    pub high_watermark: usize,
+    pub rng: RngConfig,
+    pub options: GenOptions,
+}
+
+/** A snapshot of one queue's metrics.  Hits are takes served from the
+ * queue and misses takes that found it empty and generated a key on the
+ * caller's thread.  Refills counts the times the queue dropped below the low
+ * watermark, and refill summarizes how long the refill thread took to
+ * generate each key.
+ */
+#[derive(Clone, Debug)]
+pub struct PoolStats {
+    pub spec: KeySpec,
+    pub available: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub refills: u64,
+    pub generated: u64,
+    pub refill: Option<Summary>,
+    pub failures: Failures,
+}
+
+impl PoolStats {
+    // The fraction of takes served from the queue.
+    pub fn hit_rate(&self) -> f64 {
+        let takes = self.hits + self.misses;
+        if takes == 0 {0.0} else {self.hits as f64 / takes as f64}
+    }
+}
+
+/** A pool of pre-generated keys, so that callers can take a key instantly
+ * instead of waiting for it to be generated, which matters most for RSA.
+ * A background thread keeps every queue between its watermarks and stops
+ * when the pool is dropped.
+ *
+ * ```no_run
+ * # use sshkeytest::{GenOptions, KeyPool, PoolConfig, RngConfig, RngKind};
+ * # fn main() -> Result<(), Box<dyn std::error::Error>> {
+ * let rng = RngConfig {kind: RngKind::Os, seed: None};
+ * let pool = KeyPool::new(PoolConfig {specs: vec!["rsa-3072".parse()?], low_watermark: 2,
+ *                                     high_watermark: 8, rng, options: GenOptions::default()})?;
+ * let key = pool.take(&"rsa-3072".parse()?)?;
+ * # Ok(())
+ * # }
+ * ```
+ */
+pub struct KeyPool {
+    shared: Arc<Shared>,
+    refill_thread: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    config: PoolConfig,
+    state: Mutex<State>,
+    // Signalled when a queue drops below its low watermark or on shutdown.
+    refill_needed: Condvar,
+    // Signalled whenever a key is added to a queue.
+    key_added: Condvar,
+    // RNG streams for keys generated on callers' threads; the refill thread uses stream 0.
+    next_stream: AtomicU64,
+    // Keys numbered so far for each spec the pool wasn't configured with.
+    unpooled_numbered: Mutex<Vec<(KeySpec, u32)>>,
+}
+
+struct State {
+    queues: Vec<Queue>,
+    shutdown: bool,
+}
+
+#[derive(Default)]
+struct Queue {
+    keys: VecDeque<PrivateKey>,
+    refilling: bool,
+    // Keys numbered for this queue's spec so far, by the refill thread or on
+    // a miss; comment indexes count from 1 as in gen_ssh_keys().
+    numbered: u32,
+    hits: u64,
+    misses: u64,
+    refills: u64,
+    generated: u64,
+    refill_samples: Vec<Duration>,
+    failures: Failures,
+}
+
+impl KeyPool {
+    // Create the pool and start filling every queue to its high watermark.
+    // Fails if the high watermark is 0 or below the low watermark, or the
+    // refill thread can't be started.
+    pub fn new(config: PoolConfig) -> io::Result<KeyPool> {
+        if config.high_watermark == 0 || config.low_watermark > config.high_watermark {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                      format!("key pool high watermark {} must be at least 1 and at least \
+                                               the low watermark {}",
+                                              config.high_watermark, config.low_watermark)));
+        }
+        let queues = config.specs.iter().map(|_| Queue {refilling: true, ..Default::default()}).collect();
+        let shared = Arc::new(Shared {
+            config,
+            state: Mutex::new(State {queues, shutdown: false}),
+            refill_needed: Condvar::new(),
+            key_added: Condvar::new(),
+            next_stream: AtomicU64::new(1),
+            unpooled_numbered: Mutex::new(Vec::new()),
+        });
+        let refill_shared = Arc::clone(&shared);
+        let refill_thread = thread::Builder::new()
+            .name("key-pool-refill".to_string())
+            .spawn(move || refill(&refill_shared))?;
+        Ok(KeyPool {shared, refill_thread: Some(refill_thread)})
+    }
+
+    // Take a key for the spec: from its queue if there is one waiting,
+    // otherwise generated on the calling thread.  Specs the pool wasn't
+    // configured with are always generated on the calling thread.
+    pub fn take(&self, spec: &KeySpec) -> Result<PrivateKey, KeyError> {
+        let Some(index) = self.shared.config.specs.iter().position(|s| s == spec) else {
+            let first_index = self.shared.number_unpooled(spec);
+            return self.generate(spec, first_index);
+        };
+
+        let mut state = self.shared.lock();
+        let queue = &mut state.queues[index];
+        let key = queue.keys.pop_front();
+        let first_index = queue.numbered;
+        match key {
+            Some(_) => queue.hits += 1,
+            None => {
+                queue.misses += 1;
+                queue.numbered += 1;
+            }
+        }
+        if queue.keys.len() < self.shared.config.low_watermark && !queue.refilling {
+            queue.refilling = true;
+            queue.refills += 1;
+            self.shared.refill_needed.notify_one();
+        }
+        drop(state);
+
+        match key {
+            Some(key) => Ok(key),
+            None => self.generate(spec, first_index),
+        }
+    }
+
+    // Wait until every queue has reached its high watermark, or the timeout
+    // passes, returning whether the pool is full.  A timeout too long to add
+    // to the clock waits for as long as it takes.
+    pub fn wait_full(&self, timeout: Duration) -> bool {
+        let deadline = Instant::now().checked_add(timeout);
+        let high = self.shared.config.high_watermark;
+        let mut state = self.shared.lock();
+        while !state.queues.iter().all(|q| q.keys.len() >= high) {
+            let Some(deadline) = deadline else {
+                state = self.shared.key_added.wait(state).expect("key pool lock poisoned");
+                continue;
+            };
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return false;
+            }
+            state = self.shared.key_added.wait_timeout(state, remaining)
+                .expect("key pool lock poisoned").0;
+        }
+        true
+    }
+
+    pub fn stats(&self) -> Vec<PoolStats> {
+        let state = self.shared.lock();
+        self.shared.config.specs.iter().zip(&state.queues)
+            .map(|(spec, q)| PoolStats {
+                spec: spec.clone(),
+                available: q.keys.len(),
+                hits: q.hits,
+                misses: q.misses,
+                refills: q.refills,
+                generated: q.generated,
+                refill: Summary::from_samples(&q.refill_samples),
+                failures: q.failures.clone(),
+            })
+            .collect()
+    }
+
+    // Generate a key on the calling thread the way the refill thread does,
+    // numbered first_index + 1.
+    fn generate(&self, spec: &KeySpec, first_index: u32) -> Result<PrivateKey, KeyError> {
+        let stream = self.shared.next_stream.fetch_add(1, Ordering::Relaxed);
+        let mut rng = self.shared.config.rng.new_rng(stream);
+        let mut run = gen_ssh_keys(1, &mut rng, spec, &GenOptions {first_index, ..self.shared.gen_options()});
+        match run.keys.pop() {
+            Some(key) => Ok(key),
+            None => Err(run.error.expect("gen_ssh_keys records the error that cost a key")),
+        }
+    }
+}
+
+impl Drop for KeyPool {
+    fn drop(&mut self) {
+        self.shared.lock().shutdown = true;
+        self.shared.refill_needed.notify_all();
+        if let Some(thread) = self.refill_thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("key pool lock poisoned")
+    }
+
+    // Number the next key of a spec the pool wasn't configured with, returning
+    // how many of its keys were numbered before it.
+    fn number_unpooled(&self, spec: &KeySpec) -> u32 {
+        let mut numbered = self.unpooled_numbered.lock().expect("key pool lock poisoned");
+        let index = match numbered.iter().position(|(s, _)| s == spec) {
+            Some(index) => index,
+            None => {
+                numbered.push((spec.clone(), 0));
+                numbered.len() - 1
+            }
+        };
+        numbered[index].1 += 1;
+        numbered[index].1 - 1
+    }
+
+    // The options every key is generated with, by the refill thread or on a
+    // miss, before its index is set.
+    fn gen_options(&self) -> GenOptions {
+        GenOptions {
+            print_first: false,
+            export: None,
+            encrypt: None,
+            collect_keys: true,
+            ..self.config.options.clone()
+        }
+    }
+}
+
+// The refill thread: generate one key at a time for whichever refilling queue
+// has the fewest keys, so that no queue starves while another is drained,
+// until every queue is back at its high watermark, then sleep until woken.
+fn refill(shared: &Shared) {
+    let options = shared.gen_options();
+    let mut rng = shared.config.rng.new_rng(0);
+    let high = shared.config.high_watermark;
+
+    let mut state = shared.lock();
+    loop {
+        if state.shutdown {
+            return;
+        }
+        let emptiest = state.queues.iter().enumerate()
+            .filter(|(_, q)| q.refilling)
+            .min_by_key(|(_, q)| q.keys.len());
+        let Some((index, _)) = emptiest else {
+            state = shared.refill_needed.wait(state).expect("key pool lock poisoned");
+            continue;
+        };
+        let first_index = state.queues[index].numbered;
+        state.queues[index].numbered += 1;
+        drop(state);
+
+        let spec = &shared.config.specs[index];
+        let run = gen_ssh_keys(1, &mut rng, spec, &GenOptions {first_index, ..options.clone()});
+
+        state = shared.lock();
+        let queue = &mut state.queues[index];
+        queue.generated += run.keys.len() as u64;
+        queue.refill_samples.extend(run.samples);
+        if queue.refill_samples.len() > MAX_SAMPLES {
+            queue.refill_samples.drain(..MAX_SAMPLES / 2);
+        }
+        queue.failures.merge(&run.failures);
+        // Give up on this refill if the key couldn't be generated rather than
+        // spinning; the next take below the low watermark starts another.
+        if run.keys.is_empty() || queue.keys.len() + run.keys.len() >= high {
+            queue.refilling = false;
+        }
+        queue.keys.extend(run.keys);
+        shared.key_added.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::RngKind;
+    use std::collections::BTreeSet;
+
+    fn config(specs: Vec<KeySpec>, low_watermark: usize, high_watermark: usize) -> PoolConfig {
+        let options = GenOptions {comment: Some("key {index}".parse().expect("template")), ..Default::default()};
+        PoolConfig {specs, low_watermark, high_watermark, rng: RngConfig {kind: RngKind::Os, seed: None}, options}
+    }
+
+    #[test]
+    fn rejects_watermarks_out_of_order() {
+        let error = KeyPool::new(config(vec![], 4, 2)).err().expect("watermarks out of order");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn rejects_a_high_watermark_of_0() {
+        let error = KeyPool::new(config(vec![], 0, 0)).err().expect("high watermark of 0");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    // Keys taken faster than the refill thread generates them are a mix of
+    // pooled keys and misses, and every one must still get its own index.
+    #[test]
+    fn numbers_pooled_and_missed_keys_uniquely() {
+        let spec: KeySpec = "ed25519".parse().expect("spec");
+        let pool = KeyPool::new(config(vec![spec.clone()], 1, 2)).expect("pool");
+        let comments: BTreeSet<_> = (0..20)
+            .map(|_| pool.take(&spec).expect("key").comment().to_string())
+            .collect();
+        assert_eq!(comments.len(), 20, "{:?}", comments);
+    }
+
+    #[test]
+    fn numbers_unpooled_specs_from_one() {
+        let pool = KeyPool::new(config(vec![], 0, 1)).expect("pool");
+        let spec: KeySpec = "ed25519".parse().expect("spec");
+        let comments: Vec<_> = (0..3).map(|_| pool.take(&spec).expect("key").comment().to_string()).collect();
+        assert_eq!(comments, ["key 1", "key 2", "key 3"]);
+    }
+
+    #[test]
+    fn numbers_each_unpooled_spec_separately() {
+        let pool = KeyPool::new(config(vec![], 0, 1)).expect("pool");
+        let specs: [KeySpec; 2] = ["ed25519".parse().expect("spec"), "ecdsa-p256".parse().expect("spec")];
+        let comments: Vec<_> = [&specs[0], &specs[1], &specs[1], &specs[0]].into_iter()
+            .map(|spec| pool.take(spec).expect("key").comment().to_string())
+            .collect();
+        assert_eq!(comments, ["key 1", "key 1", "key 2", "key 2"]);
+    }
+
+    #[test]
+    fn waits_for_a_timeout_too_long_for_the_clock() {
+        let pool = KeyPool::new(config(vec!["ed25519".parse().expect("spec")], 1, 2)).expect("pool");
+        assert!(pool.wait_full(Duration::MAX));
+    }
+}
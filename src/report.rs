@@ -0,0 +1,284 @@
+use serde::{Deserialize, Serialize};
+use std::{fs, io, path::Path, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use crate::alg::KeySpec;
+use crate::budget::{Budget, BudgetRun, StopReason};
+use crate::encrypt::EncryptOptions;
+use crate::error::Failures;
+use crate::parallel::ParallelRun;
+use crate::rng::RngConfig;
+use crate::stats::Summary;
+
+/** A machine-readable record of one benchmark run.  All durations are reported
+ * in nanoseconds so that downstream tools don't have to parse Rust's Debug
+ * formatting of Duration.
+ */
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Report {
+    pub timestamp: u64,     // seconds since the unix epoch
+    pub build_profile: String,
+    pub rng: String,
+    pub seed: Option<String>,
+    pub results: Vec<AlgResult>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct AlgResult {
+    pub family: String,
+    pub algorithm: String,
+    pub curve: Option<String>,
+    pub hash: Option<String>,
+    pub key_size: u32,
+    pub iterations: u32,
+    pub threads: usize,
+    pub total_ns: u64,
+    pub keys_per_sec: f64,
+    pub summary: SummaryNs,
+    pub samples_ns: Vec<u64>,
+    #[serde(default)]
+    pub failures: Failures,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encryption: Option<EncryptionResult>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub operations: Vec<OpResult>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scaling: Vec<ScalingPoint>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget: Option<BudgetResult>,
+}
+
+// Time taken to passphrase protect each key and to decrypt it again.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EncryptionResult {
+    pub cipher: String,
+    pub kdf: String,
+    pub kdf_rounds: u32,
+    pub encrypt: SummaryNs,
+    pub decrypt: SummaryNs,
+    pub encrypt_samples_ns: Vec<u64>,
+    pub decrypt_samples_ns: Vec<u64>,
+}
+
+// Time taken by another per-key operation, such as issuing a certificate.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OpResult {
+    pub operation: String,
+    pub summary: SummaryNs,
+    pub samples_ns: Vec<u64>,
+}
+
+// How a time-budgeted run went: its budget, the warm-up keys discarded and why
+// it stopped, with the final relative error of the 95% confidence interval.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct BudgetResult {
+    pub time_budget_ns: u64,
+    pub target_error: Option<f64>,
+    pub warmup_keys: usize,
+    pub stop: StopReason,
+    pub relative_error: Option<f64>,
+}
+
+// Throughput at one thread count, relative to a single thread.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ScalingPoint {
+    pub threads: usize,
+    pub keys: usize,
+    pub wall_ns: u64,
+    pub keys_per_sec: f64,
+    pub speedup: f64,
+    pub efficiency: f64,
+    pub per_thread: Vec<SummaryNs>,
+}
+
+// All zeros, with a count of 0, when no keys could be generated.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SummaryNs {
+    pub count: usize,
+    pub min_ns: u64,
+    pub max_ns: u64,
+    pub mean_ns: u64,
+    pub median_ns: u64,
+    pub stddev_ns: u64,
+    pub p90_ns: u64,
+    pub p99_ns: u64,
+    pub p999_ns: u64,
+}
+
+impl Report {
+    pub fn new(rng: &RngConfig) -> Report {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let build_profile = if cfg!(debug_assertions) {"debug"} else {"release"};
+        Report {
+            timestamp,
+            build_profile: build_profile.to_string(),
+            rng: rng.kind.to_string(),
+            seed: rng.seed.map(|s| s.to_string()),
+            results: Vec::new(),
+        }
+    }
+
+    pub fn read_json(path: &Path) -> io::Result<Report> {
+        let json = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+
+    pub fn write_json(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json + "\n")
+    }
+
+    // One row per algorithm with its summary statistics.
+    pub fn write_csv(&self, path: &Path) -> io::Result<()> {
+        let mut csv = String::from("timestamp,build_profile,rng,seed,family,algorithm,curve,hash,key_size,\
+            iterations,threads,total_ns,keys_per_sec,min_ns,max_ns,mean_ns,median_ns,stddev_ns,p90_ns,p99_ns,p999_ns,\
+            failed_keys,retries,errors,kdf_rounds,encrypt_median_ns,decrypt_median_ns\n");
+        for r in &self.results {
+            let s = &r.summary;
+            let errors: Vec<String> = r.failures.errors.iter()
+                .map(|(category, count)| format!("{}={}", category, count))
+                .collect();
+            let (kdf_rounds, encrypt_ns, decrypt_ns) = match &r.encryption {
+                Some(e) => (e.kdf_rounds.to_string(), e.encrypt.median_ns.to_string(),
+                            e.decrypt.median_ns.to_string()),
+                None => Default::default(),
+            };
+            csv += &format!("{},{},{},{},{},{},{},{},{},{},{},{},{:.3},{},{},{},{},{},{},{},{},{},{},\"{}\",{},{},{}\n",
+                self.timestamp, self.build_profile, self.rng, self.seed.as_deref().unwrap_or(""),
+                r.family, r.algorithm, r.curve.as_deref().unwrap_or(""), r.hash.as_deref().unwrap_or(""),
+                r.key_size, r.iterations, r.threads, r.total_ns, r.keys_per_sec, s.min_ns, s.max_ns,
+                s.mean_ns, s.median_ns, s.stddev_ns, s.p90_ns, s.p99_ns, s.p999_ns,
+                r.failures.failed_keys, r.failures.retries, errors.join(";").replace('"', "\"\""),
+                kdf_rounds, encrypt_ns, decrypt_ns);
+        }
+        fs::write(path, csv)
+    }
+
+    // One row per algorithm and per-key operation with its summary statistics.
+    pub fn write_ops_csv(&self, path: &Path) -> io::Result<()> {
+        let mut csv = String::from("timestamp,build_profile,algorithm,key_size,operation,count,\
+            min_ns,max_ns,mean_ns,median_ns,stddev_ns,p90_ns,p99_ns,p999_ns\n");
+        for r in &self.results {
+            for op in &r.operations {
+                let s = &op.summary;
+                csv += &format!("{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+                    self.timestamp, self.build_profile, r.algorithm, r.key_size, op.operation,
+                    s.count, s.min_ns, s.max_ns, s.mean_ns, s.median_ns, s.stddev_ns, s.p90_ns,
+                    s.p99_ns, s.p999_ns);
+            }
+        }
+        fs::write(path, csv)
+    }
+
+    // One row per generated key with the time it took to generate.
+    pub fn write_samples_csv(&self, path: &Path) -> io::Result<()> {
+        let mut csv = String::from("algorithm,key_size,index,duration_ns\n");
+        for r in &self.results {
+            for (i, ns) in r.samples_ns.iter().enumerate() {
+                csv += &format!("{},{},{},{}\n", r.algorithm, r.key_size, i, ns);
+            }
+        }
+        fs::write(path, csv)
+    }
+}
+
+impl AlgResult {
+    pub fn new(spec: &KeySpec, run: &ParallelRun, samples: &[Duration], summary: Option<&Summary>) -> AlgResult {
+        AlgResult {
+            family: spec.family.name().to_string(),
+            algorithm: spec.algorithm.to_string(),
+            curve: spec.curve().map(|c| c.to_string()),
+            hash: spec.hash().map(|h| h.to_string()),
+            key_size: spec.key_size,
+            iterations: (samples.len() as u64 + run.failures().failed_keys) as u32,
+            threads: run.threads,
+            total_ns: nanos(run.wall),
+            keys_per_sec: run.keys_per_sec(),
+            summary: summary.map(SummaryNs::from).unwrap_or_default(),
+            samples_ns: samples.iter().map(|d| nanos(*d)).collect(),
+            failures: run.failures(),
+            encryption: None,
+            operations: run.op_samples().into_iter()
+                .filter_map(|(op, samples)| Some(OpResult {
+                    operation: op,
+                    summary: SummaryNs::from(&Summary::from_samples(&samples)?),
+                    samples_ns: samples.iter().map(|d| nanos(*d)).collect(),
+                }))
+                .collect(),
+            scaling: Vec::new(),
+            budget: None,
+        }
+    }
+}
+
+impl BudgetResult {
+    pub fn new(budget: &Budget, run: &BudgetRun) -> BudgetResult {
+        BudgetResult {
+            time_budget_ns: nanos(budget.time),
+            target_error: budget.target_error,
+            warmup_keys: run.warmup_keys,
+            stop: run.stop,
+            relative_error: run.relative_error,
+        }
+    }
+}
+
+impl EncryptionResult {
+    // None if no key could be encrypted and decrypted.
+    pub fn new(options: &EncryptOptions, run: &ParallelRun) -> Option<EncryptionResult> {
+        let encrypt = run.encrypt_samples();
+        let decrypt = run.decrypt_samples();
+        Some(EncryptionResult {
+            cipher: options.cipher().to_string(),
+            kdf: "bcrypt".to_string(),
+            kdf_rounds: options.kdf_rounds,
+            encrypt: SummaryNs::from(&Summary::from_samples(&encrypt)?),
+            decrypt: SummaryNs::from(&Summary::from_samples(&decrypt)?),
+            encrypt_samples_ns: encrypt.iter().map(|d| nanos(*d)).collect(),
+            decrypt_samples_ns: decrypt.iter().map(|d| nanos(*d)).collect(),
+        })
+    }
+}
+
+impl ScalingPoint {
+    // Convert a scaling curve, measuring speedup against its first run.
+    pub fn from_runs(runs: &[ParallelRun]) -> Vec<ScalingPoint> {
+        let base = runs.first().map(|r| r.keys_per_sec()).unwrap_or(0.0);
+        runs.iter().map(|run| {
+            let speedup = run.keys_per_sec() / base;
+            ScalingPoint {
+                threads: run.threads,
+                keys: run.keys(),
+                wall_ns: nanos(run.wall),
+                keys_per_sec: run.keys_per_sec(),
+                speedup,
+                efficiency: speedup / run.threads as f64,
+                per_thread: run.per_thread.iter()
+                    .filter_map(|r| Summary::from_samples(&r.samples))
+                    .map(|s| SummaryNs::from(&s))
+                    .collect(),
+            }
+        }).collect()
+    }
+}
+
+impl From<&Summary> for SummaryNs {
+    fn from(s: &Summary) -> Self {
+        SummaryNs {
+            count: s.count,
+            min_ns: nanos(s.min),
+            max_ns: nanos(s.max),
+            mean_ns: nanos(s.mean),
+            median_ns: nanos(s.median),
+            stddev_ns: nanos(s.stddev),
+            p90_ns: nanos(s.p90),
+            p99_ns: nanos(s.p99),
+            p999_ns: nanos(s.p999),
+        }
+    }
+}
+
+fn nanos(d: Duration) -> u64 {
+    d.as_nanos() as u64
+}
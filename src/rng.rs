@@ -0,0 +1,190 @@
+use rand_chacha::{ChaCha12Rng, ChaCha20Rng, ChaCha8Rng};
+use rand_core::{CryptoRngCore, SeedableRng};
+use std::{fmt, str::FromStr};
+
+/** The random number generators that keys can be generated with.
+ *
+ *  os        - the operating system's random number generator (OsRng).
+ *  thread    - rand's secure thread-local PRNG (ChaCha based), see ThreadRng.
+ *  chacha8   - ChaCha with 8 rounds, seeded from the OS or from --seed.
+ *  chacha12  - ChaCha with 12 rounds, seeded from the OS or from --seed.
+ *  chacha20  - ChaCha with 20 rounds, seeded from the OS or from --seed.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RngKind {
+    Os,
+    Thread,
+    ChaCha8,
+    ChaCha12,
+    ChaCha20,
+}
+
+impl RngKind {
+    pub const ALL: [RngKind; 5] = [RngKind::Os, RngKind::Thread, RngKind::ChaCha8,
+                                   RngKind::ChaCha12, RngKind::ChaCha20];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RngKind::Os => "os",
+            RngKind::Thread => "thread",
+            RngKind::ChaCha8 => "chacha8",
+            RngKind::ChaCha12 => "chacha12",
+            RngKind::ChaCha20 => "chacha20",
+        }
+    }
+
+    // Only the ChaCha generators can be seeded by the user.
+    pub fn is_seedable(&self) -> bool {
+        matches!(self, RngKind::ChaCha8 | RngKind::ChaCha12 | RngKind::ChaCha20)
+    }
+}
+
+impl fmt::Display for RngKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for RngKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        RngKind::ALL.into_iter()
+            .find(|r| r.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown rng '{}' (valid options: os, thread, chacha8, chacha12, chacha20)", s))
+    }
+}
+
+/** A 32 byte ChaCha seed.  On the command line it is given either as 64 hex
+ * digits, which are used as the seed bytes, or as an unsigned integer, which
+ * is expanded into seed bytes the same way SeedableRng::seed_from_u64() does.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Seed(pub [u8; 32]);
+
+impl fmt::Display for Seed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in self.0 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Seed {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(n) = s.parse::<u64>() {
+            return Ok(Seed(ChaCha20Rng::seed_from_u64(n).get_seed()));
+        }
+        if s.len() != 64 || !s.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!("invalid seed '{}' (expected an integer or 64 hex digits)", s));
+        }
+        let mut seed = [0u8; 32];
+        for (i, b) in seed.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16)
+                .map_err(|_| format!("invalid seed '{}' (expected an integer or 64 hex digits)", s))?;
+        }
+        Ok(Seed(seed))
+    }
+}
+
+/** The generator selected for a run.  A seeded ChaCha generator produces the
+ * same keys every time it is run with the same seed, which makes it suitable
+ * for reproducible test fixtures, but it must never be used for real keys.
+ */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RngConfig {
+    pub kind: RngKind,
+    pub seed: Option<Seed>,
+}
+
+impl RngConfig {
+    // Create a new generator.  Seeded generators use the stream as the ChaCha
+    // stream number so that each worker thread gets a distinct, reproducible
+    // sequence; the cost of boxing is negligible next to generating a key.
+    pub fn new_rng(&self, stream: u64) -> Box<dyn CryptoRngCore> {
+        match (self.kind, self.seed) {
+            // Operating system's random number generator.
+            (RngKind::Os, _) => Box::new(rand::rngs::OsRng),
+            // Secure thread-safe PRNG. See rand_chacha and ThreadRng for more info.
+            (RngKind::Thread, _) => Box::new(rand::thread_rng()),
+            (RngKind::ChaCha8, None) => Box::new(ChaCha8Rng::from_entropy()),
+            (RngKind::ChaCha12, None) => Box::new(ChaCha12Rng::from_entropy()),
+            (RngKind::ChaCha20, None) => Box::new(ChaCha20Rng::from_entropy()),
+            (RngKind::ChaCha8, Some(seed)) => {
+                let mut rng = ChaCha8Rng::from_seed(seed.0);
+                rng.set_stream(stream);
+                Box::new(rng)
+            }
+            (RngKind::ChaCha12, Some(seed)) => {
+                let mut rng = ChaCha12Rng::from_seed(seed.0);
+                rng.set_stream(stream);
+                Box::new(rng)
+            }
+            (RngKind::ChaCha20, Some(seed)) => {
+                let mut rng = ChaCha20Rng::from_seed(seed.0);
+                rng.set_stream(stream);
+                Box::new(rng)
+            }
+        }
+    }
+}
+
+impl fmt::Display for RngConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.seed {
+            Some(seed) => write!(f, "{} (seed {})", self.kind, seed),
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keygen::GeneratedKey;
+
+    fn key(config: &RngConfig, stream: u64, spec: &str) -> String {
+        let mut rng = config.new_rng(stream);
+        GeneratedKey::generate(&spec.parse().expect("spec"), &mut rng).expect("key")
+            .private_key.public_key().to_openssh().expect("encoded")
+    }
+
+    #[test]
+    fn same_seed_gives_the_same_keys() {
+        let config = RngConfig {kind: RngKind::ChaCha20, seed: Some("42".parse().expect("seed"))};
+        for spec in ["ed25519", "ecdsa-p256"] {
+            assert_eq!(key(&config, 0, spec), key(&config, 0, spec), "{}", spec);
+        }
+        let other = RngConfig {seed: Some("43".parse().expect("seed")), ..config};
+        assert_ne!(key(&config, 0, "ed25519"), key(&other, 0, "ed25519"));
+    }
+
+    #[test]
+    fn streams_give_different_keys() {
+        let config = RngConfig {kind: RngKind::ChaCha20, seed: Some("42".parse().expect("seed"))};
+        assert_ne!(key(&config, 0, "ed25519"), key(&config, 1, "ed25519"));
+        assert_eq!(key(&config, 1, "ed25519"), key(&config, 1, "ed25519"));
+    }
+
+    #[test]
+    fn parses_seeds() {
+        let hex = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+        let seed: Seed = hex.parse().expect("hex seed");
+        assert_eq!(seed.0[..4], [0, 1, 2, 3]);
+        assert_eq!(seed.to_string(), hex);
+        assert_eq!("7".parse::<Seed>(), Ok(Seed(ChaCha20Rng::seed_from_u64(7).get_seed())));
+        assert_eq!(hex.to_uppercase().parse::<Seed>(), Ok(seed));
+    }
+
+    #[test]
+    fn rejects_bad_seeds() {
+        let non_ascii = format!("{}é", "0".repeat(62));
+        for bad in ["", "-1", "0x10", "18446744073709551616", &"a".repeat(63), &"a".repeat(65),
+                    &format!("{}zz", "0".repeat(62)), &non_ascii, &"+0".repeat(32)] {
+            assert!(bad.parse::<Seed>().is_err(), "accepted '{}'", bad);
+        }
+    }
+}
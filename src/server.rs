@@ -0,0 +1,238 @@
+use rand_core::CryptoRngCore;
+use serde::{Deserialize, Serialize};
+use ssh_key::{LineEnding, PrivateKey};
+use std::{io::{self, Read}, net::SocketAddr, thread};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::alg::KeySpec;
+use crate::comment;
+use crate::encrypt::EncryptOptions;
+use crate::error::KeyError;
+use crate::fingerprint::{self, FingerprintHash};
+use crate::pool::{KeyPool, PoolConfig};
+
+// The largest request body accepted; key requests are a few dozen bytes.
+const MAX_BODY_SIZE: u64 = 64 * 1024;
+
+/** The body of a POST /keys request, in the Tapis Security Kernel's camel
+ * case, e.g. {"algorithm": "rsa", "size": 3072, "comment": "alice@tapis"}.
+ * The algorithm is rsa, ecdsa or ed25519, or a full key spec such as
+ * ecdsa-p384 when size is omitted.  Size is the RSA modulus or ECDSA curve
+ * size in bits and defaults as in key specs.  The comment must not contain
+ * control characters, which would split the public key line.  A non-empty
+ * passphrase encrypts the returned private key.
+ */
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct KeyRequest {
+    pub algorithm: String,
+    #[serde(default)]
+    pub size: Option<u32>,
+    #[serde(default)]
+    pub comment: Option<String>,
+    #[serde(default)]
+    pub passphrase: Option<String>,
+}
+
+impl KeyRequest {
+    pub fn spec(&self) -> Result<KeySpec, String> {
+        let alg = self.algorithm.to_ascii_lowercase();
+        let spec = match (alg.as_str(), self.size) {
+            (_, None) => alg.clone(),
+            ("ed25519", Some(256)) => alg.clone(),
+            ("ecdsa", Some(size)) => format!("ecdsa-p{}", size),
+            ("rsa", Some(size)) => format!("rsa-{}", size),
+            (_, Some(size)) => return Err(format!("invalid size {} for algorithm '{}'", size, self.algorithm)),
+        };
+        spec.parse()
+    }
+
+    // The comment to set on the key, if there is one and it is valid.
+    pub fn checked_comment(&self) -> Result<Option<&str>, String> {
+        let comment = self.comment.as_deref();
+        comment.map(|c| comment::check_comment("comment", c)).transpose()?;
+        Ok(comment)
+    }
+}
+
+/** The generated key returned by POST /keys: the OpenSSH private key
+ * (encrypted if a passphrase was given), the public key line, its SHA-256
+ * fingerprint and the ssh-key algorithm and size it was generated with.
+ */
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyResponse {
+    pub private_key: String,
+    pub public_key: String,
+    pub fingerprint: String,
+    pub algorithm: String,
+    pub size: u32,
+}
+
+// Every response is wrapped the way the Security Kernel wraps its responses.
+#[derive(Serialize)]
+struct Envelope<T> {
+    status: &'static str,
+    message: String,
+    result: Option<T>,
+    version: &'static str,
+}
+
+/** How a KeyServer is run.  Keys come from a KeyPool built from pool, so
+ * specs listed there are served from pre-generated keys and any other spec
+ * is generated while the request waits.  Each of threads worker threads
+ * handles one request at a time.
+ */
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    pub addr: String,
+    pub threads: usize,
+    pub kdf_rounds: u32,
+    pub pool: PoolConfig,
+}
+
+/** A local stand-in for the Security Kernel's key generation endpoint,
+ * serving POST /keys over plain HTTP.
+ */
+pub struct KeyServer {
+    http: Server,
+    pool: KeyPool,
+    config: ServerConfig,
+}
+
+impl KeyServer {
+    pub fn bind(config: ServerConfig) -> io::Result<KeyServer> {
+        let http = Server::http(&config.addr).map_err(io::Error::other)?;
+        let pool = KeyPool::new(config.pool.clone())?;
+        Ok(KeyServer {http, pool, config})
+    }
+
+    // The address actually listened on, which tells callers the port when
+    // binding to port 0.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    // Serve requests on the worker threads until the listener fails.  RNG
+    // streams from u64::MAX down are used for encryption salts, leaving the
+    // low streams to the pool.
+    pub fn run(&self) {
+        thread::scope(|scope| {
+            for worker in 0..self.config.threads.max(1) {
+                scope.spawn(move || {
+                    let mut rng = self.config.pool.rng.new_rng(u64::MAX - worker as u64);
+                    while let Ok(request) = self.http.recv() {
+                        self.handle(request, &mut rng);
+                    }
+                });
+            }
+        });
+    }
+
+    fn handle(&self, mut request: Request, rng: &mut impl CryptoRngCore) {
+        let path = request.url().split('?').next().unwrap_or_default().to_string();
+        let (status, body) = match (request.method(), path.as_str()) {
+            (Method::Post, "/keys") => match read_body(&mut request).and_then(|b| self.generate(&b, rng)) {
+                Ok(key) => (200, envelope("success", "Generated key".to_string(), Some(key))),
+                Err((status, message)) => (status, envelope("error", message, None)),
+            },
+            (_, "/keys") => (405, envelope("error", "Only POST is supported on /keys".to_string(), None)),
+            _ => (404, envelope("error", format!("No such endpoint: {}", path), None)),
+        };
+        let content_type = Header::from_bytes("Content-Type", "application/json").expect("valid header");
+        // The client may have gone away; there is no one to report that to.
+        let _ = request.respond(Response::from_string(body).with_status_code(status).with_header(content_type));
+    }
+
+    // Generate the requested key, returning the HTTP status and message on failure.
+    fn generate(&self, body: &str, rng: &mut impl CryptoRngCore) -> Result<KeyResponse, (u16, String)> {
+        let request: KeyRequest = serde_json::from_str(body).map_err(|e| (400, format!("Invalid request: {}", e)))?;
+        let spec = request.spec().map_err(|e| (400, e))?;
+        let comment = request.checked_comment().map_err(|e| (400, e))?;
+        let mut key = self.pool.take(&spec).map_err(|e| (500, e.to_string()))?;
+        if let Some(comment) = comment {
+            key.set_comment(comment);
+        }
+        if let Some(passphrase) = request.passphrase.filter(|p| !p.is_empty()) {
+            key = EncryptOptions::new(passphrase, self.config.kdf_rounds).encrypt(&key, rng)
+                .map_err(|e| (500, e.to_string()))?;
+        }
+        key_response(&spec, &key).map_err(|e| (500, e.to_string()))
+    }
+}
+
+fn key_response(spec: &KeySpec, key: &PrivateKey) -> Result<KeyResponse, KeyError> {
+    Ok(KeyResponse {
+        private_key: key.to_openssh(LineEnding::LF).map_err(KeyError::EncodePrivate)?.to_string(),
+        public_key: key.public_key().to_openssh().map_err(KeyError::EncodePublic)?,
+        fingerprint: fingerprint::fingerprint(key.public_key(), FingerprintHash::Sha256)?,
+        algorithm: key.algorithm().to_string(),
+        size: spec.key_size,
+    })
+}
+
+fn read_body(request: &mut Request) -> Result<String, (u16, String)> {
+    let mut body = String::new();
+    request.as_reader().take(MAX_BODY_SIZE + 1).read_to_string(&mut body)
+        .map_err(|e| (400, format!("Unable to read request: {}", e)))?;
+    if body.len() as u64 > MAX_BODY_SIZE {
+        return Err((413, format!("Request larger than {} bytes", MAX_BODY_SIZE)));
+    }
+    Ok(body)
+}
+
+fn envelope(status: &'static str, message: String, result: Option<KeyResponse>) -> String {
+    let envelope = Envelope {status, message, result, version: env!("CARGO_PKG_VERSION")};
+    serde_json::to_string(&envelope).expect("serializable response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keygen::GenOptions;
+    use crate::rng::{RngConfig, RngKind};
+
+    fn request(algorithm: &str, size: Option<u32>, comment: Option<&str>) -> KeyRequest {
+        KeyRequest {algorithm: algorithm.to_string(), size, comment: comment.map(str::to_string), passphrase: None}
+    }
+
+    fn spec(s: &str) -> KeySpec {
+        s.parse().expect("spec")
+    }
+
+    #[test]
+    fn request_specs() {
+        assert_eq!(request("rsa", Some(3072), None).spec(), Ok(spec("rsa-3072")));
+        assert_eq!(request("RSA", None, None).spec(), Ok(spec("rsa")));
+        assert_eq!(request("ecdsa", Some(384), None).spec(), Ok(spec("ecdsa-p384")));
+        assert_eq!(request("ecdsa-p521", None, None).spec(), Ok(spec("ecdsa-p521")));
+        assert_eq!(request("ed25519", Some(256), None).spec(), Ok(spec("ed25519")));
+        assert!(request("ed25519", Some(512), None).spec().is_err());
+        assert!(request("ecdsa", Some(123), None).spec().is_err());
+        assert!(request("dsa", None, None).spec().is_err());
+    }
+
+    #[test]
+    fn request_comments() {
+        assert_eq!(request("ed25519", None, None).checked_comment(), Ok(None));
+        assert_eq!(request("ed25519", None, Some("alice@tapis")).checked_comment(), Ok(Some("alice@tapis")));
+        assert!(request("ed25519", None, Some("x\ny")).checked_comment().is_err());
+        assert!(request("ed25519", None, Some("x\ry")).checked_comment().is_err());
+    }
+
+    #[test]
+    fn rejects_comments_that_would_split_the_public_key_line() {
+        let pool = PoolConfig {specs: Vec::new(), low_watermark: 0, high_watermark: 1,
+                               rng: RngConfig {kind: RngKind::Os, seed: None}, options: GenOptions::default()};
+        let server = KeyServer::bind(ServerConfig {addr: "127.0.0.1:0".to_string(), threads: 1, kdf_rounds: 1, pool})
+            .expect("bound");
+        let mut rng = rand::rngs::OsRng;
+
+        let error = server.generate(r#"{"algorithm": "ed25519", "comment": "x\ny"}"#, &mut rng).err();
+        assert_eq!(error.map(|(status, _)| status), Some(400));
+
+        let key = server.generate(r#"{"algorithm": "ed25519", "comment": "alice@tapis"}"#, &mut rng).expect("key");
+        assert!(key.public_key.ends_with(" alice@tapis"), "{}", key.public_key);
+        assert!(!key.public_key.contains('\n'));
+    }
+}
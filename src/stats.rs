@@ -0,0 +1,291 @@
+use std::time::Duration;
+
+/** Latency statistics for a set of individually timed key generations.
+ *
+ * Percentiles are computed by linear interpolation between the two closest
+ * ranks of the sorted samples, so p50 of an even number of samples is the
+ * mean of the middle two.  The standard deviation is the sample standard
+ * deviation (n - 1 in the denominator).
+ */
+#[derive(Clone, Debug)]
+pub struct Summary {
+    pub count: usize,
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    pub median: Duration,
+    pub stddev: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub p999: Duration,
+}
+
+impl Summary {
+    // Summarize the samples, returning None if there are none.
+    pub fn from_samples(samples: &[Duration]) -> Option<Summary> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+
+        let nanos: Vec<f64> = sorted.iter().map(|d| d.as_nanos() as f64).collect();
+        let mean = nanos.iter().sum::<f64>() / nanos.len() as f64;
+        let variance = if nanos.len() > 1 {
+            nanos.iter().map(|n| (n - mean).powi(2)).sum::<f64>() / (nanos.len() - 1) as f64
+        } else {
+            0.0
+        };
+
+        Some(Summary {
+            count: sorted.len(),
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            mean: Duration::from_nanos(mean.round() as u64),
+            median: percentile(&nanos, 50.0),
+            stddev: Duration::from_nanos(variance.sqrt().round() as u64),
+            p90: percentile(&nanos, 90.0),
+            p99: percentile(&nanos, 99.0),
+            p999: percentile(&nanos, 99.9),
+        })
+    }
+
+    // Print the summary as an indented block beneath a test's output.
+    pub fn print(&self) {
+        println!("  samples: {}", self.count);
+        println!("  min:     {:?}", self.min);
+        println!("  max:     {:?}", self.max);
+        println!("  mean:    {:?}", self.mean);
+        println!("  stddev:  {:?}", self.stddev);
+        println!("  p50:     {:?}", self.median);
+        println!("  p90:     {:?}", self.p90);
+        println!("  p99:     {:?}", self.p99);
+        println!("  p99.9:   {:?}", self.p999);
+    }
+}
+
+/** The five-number summary drawn by a box plot.  The whiskers reach the most
+ * extreme samples within 1.5 times the interquartile range of the box, and
+ * samples beyond them are outliers.
+ */
+#[derive(Clone, Debug)]
+pub struct Quartiles {
+    pub min: Duration,
+    pub q1: Duration,
+    pub median: Duration,
+    pub q3: Duration,
+    pub max: Duration,
+    pub lower_whisker: Duration,
+    pub upper_whisker: Duration,
+    pub outliers: Vec<Duration>,
+}
+
+impl Quartiles {
+    // None if there are no samples.
+    pub fn from_samples(samples: &[Duration]) -> Option<Quartiles> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let nanos: Vec<f64> = sorted.iter().map(|d| d.as_nanos() as f64).collect();
+
+        let q1 = percentile(&nanos, 25.0);
+        let q3 = percentile(&nanos, 75.0);
+        let fence = (q3 - q1).mul_f64(1.5);
+        let (low, high) = (q1.saturating_sub(fence), q3 + fence);
+        let inside = |d: &&Duration| **d >= low && **d <= high;
+        Some(Quartiles {
+            min: sorted[0],
+            q1,
+            median: percentile(&nanos, 50.0),
+            q3,
+            max: sorted[sorted.len() - 1],
+            lower_whisker: *sorted.iter().find(inside).unwrap_or(&sorted[0]),
+            upper_whisker: *sorted.iter().rev().find(inside).unwrap_or(&sorted[sorted.len() - 1]),
+            outliers: sorted.iter().filter(|d| !inside(d)).copied().collect(),
+        })
+    }
+}
+
+// The pct percentile of the already sorted, non-empty nanosecond samples.
+fn percentile(sorted: &[f64], pct: f64) -> Duration {
+    let rank = pct / 100.0 * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    let weight = rank - lower as f64;
+    let value = sorted[lower] + (sorted[upper] - sorted[lower]) * weight;
+    Duration::from_nanos(value.round() as u64)
+}
+
+/** Sample counts in logarithmic latency buckets following the 1-2-5 series
+ * (1µs, 2µs, 5µs, 10µs, ...), so that latencies spread over several orders of
+ * magnitude, as RSA's do, still show their shape.  Each bucket counts the
+ * samples from lower up to but not including upper.
+ */
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    pub buckets: Vec<Bucket>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bucket {
+    pub lower: Duration,
+    pub upper: Duration,
+    pub count: usize,
+}
+
+impl Histogram {
+    // Bucket the samples, from the bucket holding the fastest sample to the
+    // one holding the slowest, returning None if there are none.
+    pub fn from_samples(samples: &[Duration]) -> Option<Histogram> {
+        let min = samples.iter().min()?.as_nanos().max(1) as u64;
+        let max = samples.iter().max()?.as_nanos().max(1) as u64;
+
+        let mut edges = vec![bucket_floor(min)];
+        while *edges.last().expect("non-empty") <= max {
+            edges.push(next_edge(*edges.last().expect("non-empty")));
+        }
+        let mut buckets: Vec<_> = edges.windows(2)
+            .map(|w| Bucket {lower: Duration::from_nanos(w[0]), upper: Duration::from_nanos(w[1]), count: 0})
+            .collect();
+        for sample in samples {
+            let ns = sample.as_nanos().max(1) as u64;
+            let i = edges.partition_point(|&e| e <= ns) - 1;
+            buckets[i].count += 1;
+        }
+        Some(Histogram {buckets})
+    }
+
+    pub fn total(&self) -> usize {
+        self.buckets.iter().map(|b| b.count).sum()
+    }
+}
+
+// The largest 1-2-5 series value no larger than ns.
+fn bucket_floor(ns: u64) -> u64 {
+    let mut decade = 1;
+    while decade * 10 <= ns {
+        decade *= 10;
+    }
+    [5, 2, 1].into_iter().map(|m| m * decade).find(|&e| e <= ns).unwrap_or(decade)
+}
+
+// The 1-2-5 series value after edge.
+fn next_edge(edge: u64) -> u64 {
+    let mut decade = 1;
+    while decade * 10 <= edge {
+        decade *= 10;
+    }
+    match edge / decade {
+        1 => 2 * decade,
+        2 => 5 * decade,
+        _ => 10 * decade,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ns(values: &[u64]) -> Vec<Duration> {
+        values.iter().map(|n| Duration::from_nanos(*n)).collect()
+    }
+
+    #[test]
+    fn percentile_interpolates_between_ranks() {
+        let sorted = [10.0, 20.0, 30.0, 40.0];
+        assert_eq!(percentile(&sorted, 50.0), Duration::from_nanos(25));
+        assert_eq!(percentile(&sorted, 25.0), Duration::from_nanos(18));
+        assert_eq!(percentile(&sorted, 90.0), Duration::from_nanos(37));
+    }
+
+    #[test]
+    fn percentile_extremes_are_min_and_max() {
+        let sorted = [3.0, 5.0, 8.0];
+        assert_eq!(percentile(&sorted, 0.0), Duration::from_nanos(3));
+        assert_eq!(percentile(&sorted, 100.0), Duration::from_nanos(8));
+    }
+
+    #[test]
+    fn percentile_of_one_sample_is_that_sample() {
+        for pct in [0.0, 50.0, 99.9, 100.0] {
+            assert_eq!(percentile(&[7.0], pct), Duration::from_nanos(7));
+        }
+    }
+
+    #[test]
+    fn summary_of_no_samples_is_none() {
+        assert!(Summary::from_samples(&[]).is_none());
+    }
+
+    #[test]
+    fn summary_of_one_sample() {
+        let s = Summary::from_samples(&ns(&[42])).expect("one sample");
+        assert_eq!(s.count, 1);
+        for d in [s.min, s.max, s.mean, s.median, s.p90, s.p99, s.p999] {
+            assert_eq!(d, Duration::from_nanos(42));
+        }
+        assert_eq!(s.stddev, Duration::ZERO);
+    }
+
+    #[test]
+    fn summary_sorts_and_uses_sample_stddev() {
+        let s = Summary::from_samples(&ns(&[9, 2, 5, 4, 12, 7, 8, 11, 9, 3])).expect("samples");
+        assert_eq!(s.count, 10);
+        assert_eq!(s.min, Duration::from_nanos(2));
+        assert_eq!(s.max, Duration::from_nanos(12));
+        assert_eq!(s.mean, Duration::from_nanos(7));
+        assert_eq!(s.median, Duration::from_nanos(8));
+        // Sum of squared deviations is 100, over n - 1 = 9.
+        assert_eq!(s.stddev, Duration::from_nanos(3));
+        assert_eq!(s.p90, Duration::from_nanos(11));
+    }
+
+    #[test]
+    fn bucket_floor_follows_the_1_2_5_series() {
+        for (ns, floor) in [(0, 1), (1, 1), (2, 2), (4, 2), (5, 5), (9, 5), (10, 10), (19, 10), (20, 20),
+                            (49, 20), (50, 50), (999, 500), (1000, 1000), (1_999_999, 1_000_000)] {
+            assert_eq!(bucket_floor(ns), floor, "bucket_floor({})", ns);
+        }
+    }
+
+    #[test]
+    fn next_edge_follows_the_1_2_5_series() {
+        for (edge, next) in [(1, 2), (2, 5), (5, 10), (10, 20), (20, 50), (50, 100), (500_000, 1_000_000),
+                             (1_000_000, 2_000_000)] {
+            assert_eq!(next_edge(edge), next, "next_edge({})", edge);
+        }
+    }
+
+    #[test]
+    fn histogram_counts_every_sample_once() {
+        let samples = ns(&[1500, 2000, 7000, 12_000, 12_500, 45_000, 50_000]);
+        let h = Histogram::from_samples(&samples).expect("samples");
+        let edges: Vec<_> = h.buckets.iter().map(|b| (b.lower.as_nanos(), b.upper.as_nanos())).collect();
+        assert_eq!(edges, [(1000, 2000), (2000, 5000), (5000, 10_000), (10_000, 20_000), (20_000, 50_000),
+                           (50_000, 100_000)]);
+        assert_eq!(h.buckets.iter().map(|b| b.count).collect::<Vec<_>>(), [1, 1, 1, 2, 1, 1]);
+        assert_eq!(h.total(), samples.len());
+    }
+
+    #[test]
+    fn histogram_of_no_samples_is_none() {
+        assert!(Histogram::from_samples(&[]).is_none());
+    }
+
+    #[test]
+    fn quartile_fences() {
+        let micros: Vec<_> = [10, 20, 30, 40, 50, 60, 70, 80, 90, 1000].iter().map(|n| n * 1000).collect();
+        let q = Quartiles::from_samples(&ns(&micros)).expect("samples");
+        assert_eq!(q.q1, Duration::from_nanos(32_500));
+        assert_eq!(q.median, Duration::from_nanos(55_000));
+        assert_eq!(q.q3, Duration::from_nanos(77_500));
+        // The upper fence is q3 + 1.5 * 45µs = 145µs, so only 1ms is beyond it.
+        assert_eq!(q.lower_whisker, Duration::from_micros(10));
+        assert_eq!(q.upper_whisker, Duration::from_micros(90));
+        assert_eq!(q.outliers, [Duration::from_micros(1000)]);
+        assert_eq!((q.min, q.max), (Duration::from_micros(10), Duration::from_micros(1000)));
+    }
+}
@@ -0,0 +1,105 @@
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use ssh_key::{private::{EcdsaKeypair, KeypairData}, public::{Ed25519PublicKey, KeyData},
+              HashAlg, LineEnding, PrivateKey, PublicKey};
+use std::ops::Deref;
+
+use crate::alg;
+use crate::error::KeyError;
+use crate::fingerprint::{self, FingerprintHash};
+
+// The namespace and message the test signature is made with.
+const NAMESPACE: &str = "sshkeytest-verify";
+const MESSAGE: &[u8] = b"sshkeytest round trip verification";
+
+/** Check that a key survives serialization intact: the OpenSSH private and
+ * public encodings parse back to keys equal to the original, the public key
+ * derived from the private key matches the stored one, and a signature made
+ * with the re-parsed private key verifies with the re-parsed public key.
+ * RSA keys above RSA_VERIFY_MAX_BITS can't verify signatures, so for them the
+ * signature check is skipped and the rest of the round trip still checked.
+ */
+pub fn verify_round_trip(key: &PrivateKey) -> Result<(), KeyError> {
+    let pem = key.to_openssh(LineEnding::LF).map_err(KeyError::EncodePrivate)?;
+    let parsed = PrivateKey::from_openssh(pem.as_bytes()).map_err(KeyError::DecodePrivate)?;
+    if parsed != *key {
+        return Err(KeyError::Mismatch("re-parsed private key"));
+    }
+
+    let line = key.public_key().to_openssh().map_err(KeyError::EncodePublic)?;
+    let public = PublicKey::from_openssh(&line).map_err(KeyError::DecodePublic)?;
+    if public != *key.public_key() {
+        return Err(KeyError::Mismatch("re-parsed public key"));
+    }
+
+    if !derived_public_matches(key) {
+        return Err(KeyError::Mismatch("derived public key"));
+    }
+
+    if !alg::can_verify(public.key_data()) {
+        return Ok(());
+    }
+    let sig = parsed.sign(NAMESPACE, HashAlg::Sha512, MESSAGE).map_err(KeyError::Sign)?;
+    public.verify(NAMESPACE, MESSAGE, &sig).map_err(KeyError::Verify)
+}
+
+// Recompute the public key from the private key material and compare it with
+// both the keypair's public half and the key's stored public key.  For RSA the
+// public key is the modulus, so check that it is the product of the primes.
+fn derived_public_matches(key: &PrivateKey) -> bool {
+    let derived = match key.key_data() {
+        KeypairData::Ed25519(kp) => Ed25519PublicKey::from(&kp.private) == kp.public,
+        KeypairData::Ecdsa(EcdsaKeypair::NistP256 {public, private}) =>
+            p256::SecretKey::from_slice(private.as_slice())
+                .is_ok_and(|sk| sk.public_key().to_encoded_point(false) == *public),
+        KeypairData::Ecdsa(EcdsaKeypair::NistP384 {public, private}) =>
+            p384::SecretKey::from_slice(private.as_slice())
+                .is_ok_and(|sk| sk.public_key().to_encoded_point(false) == *public),
+        KeypairData::Ecdsa(EcdsaKeypair::NistP521 {public, private}) =>
+            p521::SecretKey::from_slice(private.as_slice())
+                .is_ok_and(|sk| sk.public_key().to_encoded_point(false) == *public),
+        KeypairData::Rsa(kp) => {
+            let n = rsa::BigUint::try_from(&kp.public.n);
+            let p = rsa::BigUint::try_from(&kp.private.p);
+            let q = rsa::BigUint::try_from(&kp.private.q);
+            matches!((n, p, q), (Ok(n), Ok(p), Ok(q)) if n == &p * &q)
+        }
+        _ => false,
+    };
+    derived && KeyData::try_from(key.key_data()).is_ok_and(|k| k == *key.public_key().key_data())
+}
+
+// Dump a key that failed verification to stderr for triage.  These are
+// throwaway benchmark keys, so the private key is included when with_private
+// is set; callers leave it out when the keys were to be passphrase protected.
+pub fn dump_key(key: &PrivateKey, error: &KeyError, with_private: bool) {
+    eprintln!("------- Round trip verification failed: {}", error);
+    if with_private {
+        match key.to_openssh(LineEnding::LF) {
+            Ok(pem) => eprintln!("{}", pem.deref()),
+            Err(_) => eprintln!("{:?}", key.key_data()),
+        }
+    }
+    match key.public_key().to_openssh() {
+        Ok(line) => eprintln!("{}", line),
+        Err(_) => eprintln!("{:?}", key.public_key().key_data()),
+    }
+    match fingerprint::fingerprint(key.public_key(), FingerprintHash::Sha256) {
+        Ok(fp) => eprintln!("{}", fp),
+        Err(e) => eprintln!("Unable to {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_keys;
+
+    // Keys too large for the rsa crate to verify with still pass the rest of
+    // the round trip.
+    #[test]
+    fn rsa_keys_above_the_verify_limit_pass() {
+        let key = PrivateKey::from_openssh(test_keys::RSA_4160).expect("test key");
+        assert!(!alg::can_verify(key.public_key().key_data()));
+        verify_round_trip(&key).expect("round trip");
+    }
+}